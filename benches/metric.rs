@@ -0,0 +1,110 @@
+//! Full-HD timings for the colour-distance kernel.
+//!
+//! Run with `cargo bench`. `uncached` is the kernel as it was before identical pixels were
+//! skipped and conversions memoized: both pixels are converted to Lab for every pair. The
+//! `kernel` rows feed every pixel pair to the metric, so neither side benefits from the
+//! identical-row fast path; the `compare` rows time a full `compare_buffers_with_metric` call.
+
+use image::{Rgba, RgbaImage};
+use image_diff::{compare_buffers_with_metric, CompareOptions, Metric, PixelMetric, SsimOptions};
+use lab::Lab;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const RUNS: usize = 5;
+
+struct Uncached;
+
+impl PixelMetric for Uncached {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        let lab = |p: &Rgba<u8>| Lab::from_rgb(&[p[0], p[1], p[2]]);
+        let alpha = (p1[3] as f64 - p2[3] as f64).abs() / 2.55;
+        (delta_e::DE2000::new(lab(p1), lab(p2)) as f64 + alpha) / 100.0
+    }
+}
+
+// A UI-like frame: flat panels, a gradient header and rows of pseudo-random "text"
+fn screenshot(seed: u32) -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        if y < 64 {
+            return Rgba([30, 60 + (x * 120 / WIDTH) as u8, 160, 255]);
+        }
+        if x < 280 {
+            return Rgba([36, 39, 46, 255]);
+        }
+        let hash = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663) ^ seed).wrapping_mul(2_654_435_761);
+        let in_text_line = (y % 24) < 14 && x % 640 < 560;
+        if in_text_line && hash.is_multiple_of(5) {
+            let ink = 40 + (hash >> 24) as u8 % 80;
+            Rgba([ink, ink, ink, 255])
+        } else {
+            Rgba([250, 250, 250, 255])
+        }
+    })
+}
+
+type Timing = fn(&RgbaImage, &RgbaImage, &dyn PixelMetric) -> Duration;
+
+fn median(mut run: impl FnMut()) -> Duration {
+    let mut runs: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .collect();
+    runs.sort();
+    runs[RUNS / 2]
+}
+
+// Every pixel pair through the metric on one thread, as the pre-change kernel saw them
+fn time_kernel(img_a: &RgbaImage, img_b: &RgbaImage, metric: &dyn PixelMetric) -> Duration {
+    median(|| {
+        let sum: f64 = img_a.pixels().zip(img_b.pixels()).map(|(p1, p2)| metric.distance(p1, p2)).sum();
+        black_box(sum);
+    })
+}
+
+fn time_compare(img_a: &RgbaImage, img_b: &RgbaImage, metric: &dyn PixelMetric) -> Duration {
+    // SSIM is independent of the metric, so leave it out of the timing
+    let options = CompareOptions::builder().ssim(SsimOptions { enabled: false, ..SsimOptions::default() }).build();
+    median(|| {
+        black_box(compare_buffers_with_metric(img_a, img_b, &options, metric).unwrap());
+    })
+}
+
+fn main() {
+    let img_a = screenshot(0);
+
+    // A few edited widgets: most rows are byte-identical
+    let mut sparse = img_a.clone();
+    for y in 300..340 {
+        for x in 900..1300 {
+            sparse.put_pixel(x, y, Rgba([220, 40, 40, 255]));
+        }
+    }
+
+    // Re-rendered text on every line: every row reaches the metric
+    let rerendered = screenshot(1);
+    let dense = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        if x >= 1000 { *rerendered.get_pixel(x, y) } else { *img_a.get_pixel(x, y) }
+    });
+
+    println!("{:<16} {:>12} {:>12} {:>8}", "case", "uncached", "ciede2000", "speedup");
+    let timings: [(&str, Timing); 2] = [("kernel", time_kernel), ("compare", time_compare)];
+    for (kind, time) in timings {
+        for (name, img_b) in [("sparse", &sparse), ("dense", &dense)] {
+            let before = time(&img_a, img_b, &Uncached);
+            let after = time(&img_a, img_b, Metric::Ciede2000.pixel_metric());
+            println!(
+                "{:<16} {:>10.1}ms {:>10.1}ms {:>7.1}x",
+                format!("{} {}", kind, name),
+                before.as_secs_f64() * 1000.0,
+                after.as_secs_f64() * 1000.0,
+                before.as_secs_f64() / after.as_secs_f64()
+            );
+        }
+    }
+}
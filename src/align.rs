@@ -0,0 +1,193 @@
+//! Global translation registration between two images.
+//!
+//! The offset is found with a coarse-to-fine search over a luma pyramid: an exhaustive
+//! search at the coarsest level, then a ±1 pixel refinement at every finer level.
+
+use crate::compare::Region;
+use crate::size::Canvas;
+use image::buffer::ConvertBuffer;
+use image::{GrayImage, RgbaImage};
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+// Levels stop shrinking below this size so the coarse search still has structure to match
+const MIN_LEVEL_SIZE: u32 = 32;
+// Candidate offsets must overlap at least this fraction of the image
+const MIN_OVERLAP: f64 = 0.5;
+
+struct Plane {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Plane {
+    fn from_rgba(img: &RgbaImage) -> Self {
+        let luma: GrayImage = img.convert();
+        Plane {
+            width: luma.width(),
+            height: luma.height(),
+            data: luma.pixels().map(|p| p[0] as f32).collect(),
+        }
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    // 2x box downsample
+    fn half(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = ((x * 2).min(self.width - 1), (y * 2).min(self.height - 1));
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                let sum = self.get(x0, y0) + self.get(x1, y0) + self.get(x0, y1) + self.get(x1, y1);
+                data.push(sum / 4.0);
+            }
+        }
+        Plane { width, height, data }
+    }
+}
+
+/// Mean absolute luma difference when B is shifted by `(dx, dy)` relative to A.
+fn score(a: &Plane, b: &Plane, dx: i32, dy: i32) -> Option<f64> {
+    let x_start = 0.max(-dx) as u32;
+    let y_start = 0.max(-dy) as u32;
+    let x_end = (a.width as i64).min(b.width as i64 - dx as i64);
+    let y_end = (a.height as i64).min(b.height as i64 - dy as i64);
+    if x_end <= x_start as i64 || y_end <= y_start as i64 {
+        return None;
+    }
+    let (x_end, y_end) = (x_end as u32, y_end as u32);
+
+    let overlap = ((x_end - x_start) as f64) * ((y_end - y_start) as f64);
+    if overlap < MIN_OVERLAP * (a.width as f64) * (a.height as f64) {
+        return None;
+    }
+
+    let mut sum = 0.0f64;
+    for y in y_start..y_end {
+        let by = (y as i32 + dy) as u32;
+        for x in x_start..x_end {
+            let bx = (x as i32 + dx) as u32;
+            sum += (a.get(x, y) - b.get(bx, by)).abs() as f64;
+        }
+    }
+    Some(sum / overlap)
+}
+
+fn best_offset(a: &Plane, b: &Plane, center: (i32, i32), radius: i32, limit: i32) -> (i32, i32) {
+    let candidates: Vec<(i32, i32)> = (-radius..=radius)
+        .flat_map(|oy| (-radius..=radius).map(move |ox| (center.0 + ox, center.1 + oy)))
+        .filter(|(dx, dy)| dx.abs() <= limit && dy.abs() <= limit)
+        .collect();
+
+    candidates
+        .par_iter()
+        .filter_map(|&(dx, dy)| score(a, b, dx, dy).map(|s| (s, dx, dy)))
+        // Prefer the smallest shift on ties so identical images stay at (0, 0)
+        .min_by(|l, r| {
+            l.0.total_cmp(&r.0)
+                .then((l.1.abs() + l.2.abs()).cmp(&(r.1.abs() + r.2.abs())))
+        })
+        .map_or(center, |(_, dx, dy)| (dx, dy))
+}
+
+/// Finds the translation `(dx, dy)` such that `B(x + dx, y + dy) ≈ A(x, y)`,
+/// searching offsets up to `max_offset` pixels in each direction.
+pub fn find_offset(img_a: &RgbaImage, img_b: &RgbaImage, max_offset: u32) -> (i32, i32) {
+    let max_offset = max_offset as i32;
+    if max_offset == 0 {
+        return (0, 0);
+    }
+
+    let mut pyramid = vec![(Plane::from_rgba(img_a), Plane::from_rgba(img_b))];
+    loop {
+        let level = pyramid.len() as u32;
+        let (a, b) = pyramid.last().unwrap();
+        let coarse_radius = max_offset >> level;
+        if coarse_radius < 2 || a.width.min(a.height) / 2 < MIN_LEVEL_SIZE {
+            break;
+        }
+        let next = (a.half(), b.half());
+        pyramid.push(next);
+    }
+
+    let levels = pyramid.len() as u32 - 1;
+    let (a, b) = &pyramid[levels as usize];
+    let coarse_limit = max_offset >> levels;
+    let mut offset = best_offset(a, b, (0, 0), coarse_limit, coarse_limit);
+
+    for level in (0..levels).rev() {
+        let (a, b) = &pyramid[level as usize];
+        offset = best_offset(a, b, (offset.0 * 2, offset.1 * 2), 1, max_offset >> level);
+    }
+
+    offset
+}
+
+/// Shifts B on the canvas by the detected offset so it lines up with A.
+///
+/// Canvas pixels no longer covered by B become transparent padding.
+pub(crate) fn apply_offset(canvas: &mut Canvas, (dx, dy): (i32, i32)) {
+    if (dx, dy) == (0, 0) {
+        return;
+    }
+
+    let (width, height) = canvas.b.dimensions();
+    let source = &canvas.b;
+    let shifted = RgbaImage::from_fn(width, height, |x, y| {
+        let (sx, sy) = (x as i64 + dx as i64, y as i64 + dy as i64);
+        if sx >= 0 && sy >= 0 && sx < width as i64 && sy < height as i64 {
+            *source.get_pixel(sx as u32, sy as u32)
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    });
+
+    let fp = &canvas.footprint_b;
+    let x0 = (fp.x as i64 - dx as i64).clamp(0, width as i64);
+    let y0 = (fp.y as i64 - dy as i64).clamp(0, height as i64);
+    let x1 = (fp.x as i64 + fp.width as i64 - dx as i64).clamp(0, width as i64);
+    let y1 = (fp.y as i64 + fp.height as i64 - dy as i64).clamp(0, height as i64);
+
+    canvas.b = Cow::Owned(shifted);
+    canvas.footprint_b = Region {
+        x: x0 as u32,
+        y: y0 as u32,
+        width: (x1 - x0) as u32,
+        height: (y1 - y0) as u32,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn pattern(width: u32, height: u32, shift: (u32, u32)) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x + 100 - shift.0, y + 100 - shift.1);
+            let v = ((x / 7 * 31 + y / 5 * 17 + (x * y) % 13) % 256) as u8;
+            Rgba([v, v / 2, 255 - v, 255])
+        })
+    }
+
+    #[test]
+    fn test_finds_known_shift() {
+        let img_a = pattern(200, 150, (0, 0));
+        let img_b = pattern(200, 150, (3, 5));
+        assert_eq!(find_offset(&img_a, &img_b, 8), (3, 5));
+        assert_eq!(find_offset(&img_b, &img_a, 8), (-3, -5));
+    }
+
+    #[test]
+    fn test_identical_is_zero() {
+        let img = pattern(64, 64, (0, 0));
+        assert_eq!(find_offset(&img, &img, 10), (0, 0));
+        assert_eq!(find_offset(&img, &img, 0), (0, 0));
+    }
+}
@@ -0,0 +1,115 @@
+//! How the alpha channel takes part in a comparison.
+
+use crate::options::Color;
+use crate::size::Canvas;
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// Treatment of the alpha channel before pixels are compared.
+///
+/// Canvas padding is fully transparent black, so it goes through the same transform:
+/// it stays transparent under `Compare` and `Premultiply`, becomes opaque black under
+/// `Ignore`, and takes the background colour under `Composite`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlphaMode {
+    /// Compare RGBA as stored; hidden colour under transparent pixels still counts
+    #[default]
+    Compare,
+    /// Drop alpha and compare the stored RGB values as if opaque
+    Ignore,
+    /// Multiply RGB by alpha, so every fully transparent pixel is the same
+    Premultiply,
+    /// Blend both images over a solid background colour
+    Composite(Color),
+}
+
+impl FromStr for AlphaMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        if let Some(color) = lower.strip_prefix("composite=") {
+            return Ok(AlphaMode::Composite(color.parse()?));
+        }
+        match lower.as_str() {
+            "compare" => Ok(AlphaMode::Compare),
+            "ignore" => Ok(AlphaMode::Ignore),
+            "premultiply" => Ok(AlphaMode::Premultiply),
+            "composite" => Ok(AlphaMode::Composite(Color([255, 255, 255]))),
+            _ => Err(anyhow::anyhow!(
+                "Unknown alpha mode '{}' (expected compare, ignore, premultiply or composite=<color>)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AlphaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlphaMode::Compare => f.write_str("compare"),
+            AlphaMode::Ignore => f.write_str("ignore"),
+            AlphaMode::Premultiply => f.write_str("premultiply"),
+            AlphaMode::Composite(color) => write!(f, "composite={}", color),
+        }
+    }
+}
+
+impl AlphaMode {
+    fn transform(&self, p: &Rgba<u8>) -> Rgba<u8> {
+        let a = p[3] as u32;
+        match self {
+            AlphaMode::Compare => *p,
+            AlphaMode::Ignore => Rgba([p[0], p[1], p[2], 255]),
+            AlphaMode::Premultiply => {
+                let mul = |c: u8| ((c as u32 * a + 127) / 255) as u8;
+                Rgba([mul(p[0]), mul(p[1]), mul(p[2]), p[3]])
+            }
+            AlphaMode::Composite(Color(bg)) => {
+                let over = |c: u8, b: u8| ((c as u32 * a + b as u32 * (255 - a) + 127) / 255) as u8;
+                Rgba([over(p[0], bg[0]), over(p[1], bg[1]), over(p[2], bg[2]), 255])
+            }
+        }
+    }
+
+    /// Rewrites both canvas images according to the mode.
+    pub(crate) fn apply(&self, canvas: &mut Canvas) {
+        if *self == AlphaMode::Compare {
+            return;
+        }
+        for img in [&mut canvas.a, &mut canvas.b] {
+            let transformed = RgbaImage::from_fn(img.width(), img.height(), |x, y| self.transform(img.get_pixel(x, y)));
+            *img = Cow::Owned(transformed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_color_under_transparency() {
+        let p = Rgba([255, 0, 0, 0]);
+        let q = Rgba([0, 0, 255, 0]);
+
+        assert_ne!(AlphaMode::Compare.transform(&p), AlphaMode::Compare.transform(&q));
+        assert_ne!(AlphaMode::Ignore.transform(&p), AlphaMode::Ignore.transform(&q));
+        assert_eq!(AlphaMode::Premultiply.transform(&p), AlphaMode::Premultiply.transform(&q));
+
+        let white = AlphaMode::Composite(Color([255, 255, 255]));
+        assert_eq!(white.transform(&p), Rgba([255, 255, 255, 255]));
+        assert_eq!(white.transform(&Rgba([0, 0, 0, 128])), Rgba([127, 127, 127, 255]));
+    }
+
+    #[test]
+    fn test_alpha_mode_from_str() {
+        assert_eq!("composite=#000000".parse::<AlphaMode>().unwrap(), AlphaMode::Composite(Color([0, 0, 0])));
+        let mode = AlphaMode::Composite(Color([1, 2, 3]));
+        assert_eq!(mode.to_string().parse::<AlphaMode>().unwrap(), mode);
+        assert!("blend".parse::<AlphaMode>().is_err());
+    }
+}
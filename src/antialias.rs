@@ -0,0 +1,206 @@
+//! Anti-aliasing detection shared by the standard and pixelmatch comparison modes.
+
+use crate::metric::{yiq_brightness_delta, PixelMetric};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How pixels that look like anti-aliasing are treated.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AntiAliasing {
+    /// Every pixel over the threshold is a difference
+    Off,
+    /// Pixels just over the threshold whose new colour appears among the 4 neighbours in A
+    #[default]
+    Heuristic,
+    /// 8-neighbour brightness-gradient analysis of both images (Vysniauskas, 2009)
+    Full,
+}
+
+impl FromStr for AntiAliasing {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(AntiAliasing::Off),
+            "heuristic" => Ok(AntiAliasing::Heuristic),
+            "full" => Ok(AntiAliasing::Full),
+            _ => Err(anyhow::anyhow!("Unknown anti-aliasing mode '{}' (expected off, heuristic or full)", s)),
+        }
+    }
+}
+
+impl fmt::Display for AntiAliasing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AntiAliasing::Off => f.write_str("off"),
+            AntiAliasing::Heuristic => f.write_str("heuristic"),
+            AntiAliasing::Full => f.write_str("full"),
+        }
+    }
+}
+
+impl AntiAliasing {
+    /// Whether the differing pixel at `(x, y)` should be attributed to anti-aliasing.
+    ///
+    /// `distance` is the metric distance already computed for the pixel.
+    pub(crate) fn is_antialiased(
+        &self,
+        img_a: &RgbaImage,
+        img_b: &RgbaImage,
+        (x, y): (u32, u32),
+        distance: f64,
+        threshold: f64,
+        metric: &dyn PixelMetric,
+    ) -> bool {
+        match self {
+            AntiAliasing::Off => false,
+            AntiAliasing::Heuristic => {
+                distance < threshold * 1.5 && neighbour_shift(img_a, img_b, x, y, metric)
+            }
+            AntiAliasing::Full => antialiased(img_a, img_b, x, y) || antialiased(img_b, img_a, x, y),
+        }
+    }
+}
+
+fn neighbour_shift(img_a: &RgbaImage, img_b: &RgbaImage, x: u32, y: u32, metric: &dyn PixelMetric) -> bool {
+    // If a pixel is different but its value exists in the neighbour
+    // set of the other image, it's likely a sub-pixel shift.
+    let (width, height) = img_a.dimensions();
+    let neighbors = [
+        (x.saturating_sub(1), y),
+        (x + 1, y),
+        (x, y.saturating_sub(1)),
+        (x, y + 1),
+    ];
+
+    let p_b = img_b.get_pixel(x, y);
+
+    neighbors.iter().any(|&(nx, ny)| {
+        nx < width && ny < height && metric.distance(p_b, img_a.get_pixel(nx, ny)) < 0.05
+    })
+}
+
+/// Checks whether a pixel is likely part of anti-aliasing (Vysniauskas, 2009).
+///
+/// `img` is the image the pixel is taken from; `other` is the image it is compared against.
+pub(crate) fn antialiased(img: &RgbaImage, other: &RgbaImage, x1: u32, y1: u32) -> bool {
+    let (width, height) = img.dimensions();
+    let x0 = x1.saturating_sub(1);
+    let y0 = y1.saturating_sub(1);
+    let x2 = (x1 + 1).min(width - 1);
+    let y2 = (y1 + 1).min(height - 1);
+    let center = img.get_pixel(x1, y1);
+
+    // Pixels on the image edge start with one "equal" sibling
+    let mut zeroes = u32::from(x1 == x0 || x1 == x2 || y1 == y0 || y1 == y2);
+    let mut min = 0.0;
+    let mut max = 0.0;
+    let mut min_pos = (0, 0);
+    let mut max_pos = (0, 0);
+
+    for x in x0..=x2 {
+        for y in y0..=y2 {
+            if x == x1 && y == y1 {
+                continue;
+            }
+
+            // Brightness delta between the center pixel and its neighbour
+            let delta = yiq_brightness_delta(center, img.get_pixel(x, y));
+
+            if delta == 0.0 {
+                zeroes += 1;
+                // More than two equal siblings means it's not anti-aliasing
+                if zeroes > 2 {
+                    return false;
+                }
+            } else if delta < min {
+                min = delta;
+                min_pos = (x, y);
+            } else if delta > max {
+                max = delta;
+                max_pos = (x, y);
+            }
+        }
+    }
+
+    // No darker or no brighter neighbour means it's not anti-aliasing
+    if min == 0.0 || max == 0.0 {
+        return false;
+    }
+
+    // The darkest or brightest neighbour should sit in a flat area in both images
+    (has_many_siblings(img, min_pos.0, min_pos.1) && has_many_siblings(other, min_pos.0, min_pos.1))
+        || (has_many_siblings(img, max_pos.0, max_pos.1) && has_many_siblings(other, max_pos.0, max_pos.1))
+}
+
+// Checks whether a pixel has 3+ adjacent pixels of exactly the same colour
+fn has_many_siblings(img: &RgbaImage, x1: u32, y1: u32) -> bool {
+    let (width, height) = img.dimensions();
+    let x0 = x1.saturating_sub(1);
+    let y0 = y1.saturating_sub(1);
+    let x2 = (x1 + 1).min(width - 1);
+    let y2 = (y1 + 1).min(height - 1);
+    let center = img.get_pixel(x1, y1);
+
+    let mut zeroes = u32::from(x1 == x0 || x1 == x2 || y1 == y0 || y1 == y2);
+
+    for x in x0..=x2 {
+        for y in y0..=y2 {
+            if x == x1 && y == y1 {
+                continue;
+            }
+            if img.get_pixel(x, y) == center {
+                zeroes += 1;
+            }
+            if zeroes > 2 {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use image::Rgba;
+
+    /// A hard vertical edge at x = 4 in A that becomes a gray ramp pixel in B.
+    pub(crate) fn edge_pair() -> (RgbaImage, RgbaImage) {
+        let mut img_a = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        for y in 0..8 {
+            for x in 4..8 {
+                img_a.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+        let mut img_b = img_a.clone();
+        for y in 0..8 {
+            img_b.put_pixel(4, y, Rgba([128, 128, 128, 255]));
+        }
+        (img_a, img_b)
+    }
+
+    #[test]
+    fn test_full_detects_edge_ramp() {
+        let (img_a, img_b) = edge_pair();
+        let metric = Metric::default().pixel_metric();
+        let d = metric.distance(img_a.get_pixel(4, 3), img_b.get_pixel(4, 3));
+
+        assert!(AntiAliasing::Full.is_antialiased(&img_a, &img_b, (4, 3), d, 0.1, metric));
+        assert!(!AntiAliasing::Off.is_antialiased(&img_a, &img_b, (4, 3), d, 0.1, metric));
+    }
+
+    #[test]
+    fn test_full_rejects_isolated_change() {
+        let img_a = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        let mut img_b = img_a.clone();
+        img_b.put_pixel(3, 3, Rgba([0, 0, 0, 255]));
+
+        assert!(!antialiased(&img_a, &img_b, 3, 3));
+        assert!(!antialiased(&img_b, &img_a, 3, 3));
+    }
+}
@@ -0,0 +1,101 @@
+//! Grouping differing pixels into connected regions.
+
+use crate::compare::{DiffPass, Region};
+use serde::{Deserialize, Serialize};
+
+/// A connected cluster of differing pixels.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiffRegion {
+    /// Bounding box of the cluster
+    #[serde(flatten)]
+    pub region: Region,
+    /// Number of differing pixels in the cluster
+    pub pixels: u64,
+    /// Mean distance of the cluster's pixels, on the metric's 0.0-1.0 scale
+    pub mean_distance: f64,
+    pub max_distance: f64,
+}
+
+/// Labels 8-connected components of the diff mask, in scan order of their first pixel.
+pub(crate) fn diff_regions(pass: &DiffPass) -> Vec<DiffRegion> {
+    let (width, height) = (pass.width as usize, pass.height as usize);
+    let mut visited = vec![false; pass.distances.len()];
+    let mut stack = Vec::new();
+    let mut regions = Vec::new();
+
+    for start in 0..pass.distances.len() {
+        if visited[start] || !pass.different[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        stack.push(start);
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (usize::MAX, usize::MAX, 0, 0);
+        let (mut pixels, mut sum, mut max_distance) = (0u64, 0.0f64, 0.0f64);
+
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            let distance = pass.distances[i] as f64;
+
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            pixels += 1;
+            sum += distance;
+            max_distance = max_distance.max(distance);
+
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let n = ny * width + nx;
+                    if !visited[n] && pass.different[n] {
+                        visited[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+
+        regions.push(DiffRegion {
+            region: Region {
+                x: min_x as u32,
+                y: min_y as u32,
+                width: (max_x - min_x + 1) as u32,
+                height: (max_y - min_y + 1) as u32,
+            },
+            pixels,
+            mean_distance: sum / pixels as f64,
+            max_distance,
+        });
+    }
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagonal_pixels_join() {
+        let mut pass = DiffPass::new(6, 4);
+        pass.record(0, 0, 0.2);
+        pass.mark_different(0, 0);
+        pass.record(1, 1, 0.4);
+        pass.mark_different(1, 1);
+        pass.record(5, 3, 1.0);
+        pass.mark_different(5, 3);
+
+        let regions = diff_regions(&pass);
+        assert_eq!(regions.len(), 2);
+
+        let first = &regions[0];
+        assert_eq!((first.region.x, first.region.y, first.region.width, first.region.height), (0, 0, 2, 2));
+        assert_eq!(first.pixels, 2);
+        assert!((first.mean_distance - 0.3).abs() < 1e-6);
+        assert!((first.max_distance - 0.4).abs() < 1e-6);
+
+        assert_eq!((regions[1].region.x, regions[1].region.y), (5, 3));
+    }
+}
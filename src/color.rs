@@ -0,0 +1,85 @@
+//! Colour management: converting images with embedded ICC profiles to sRGB.
+//!
+//! Every metric assumes sRGB input, so images tagged with another profile (e.g. Display P3
+//! screenshots from macOS) are converted to sRGB before comparison.
+
+use image::RgbaImage;
+use moxcms::{ColorProfile, Layout, TransformOptions};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// When embedded ICC profiles are applied.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorManagement {
+    /// Convert tagged images to sRGB; a profile that cannot be applied is ignored
+    #[default]
+    Auto,
+    /// Compare raw pixel values, ignoring embedded profiles
+    Off,
+    /// Convert tagged images to sRGB and fail if a profile cannot be applied
+    Force,
+}
+
+impl FromStr for ColorManagement {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorManagement::Auto),
+            "off" => Ok(ColorManagement::Off),
+            "force" => Ok(ColorManagement::Force),
+            _ => Err(anyhow::anyhow!("Unknown colour management mode '{}' (expected auto, off or force)", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColorManagement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorManagement::Auto => f.write_str("auto"),
+            ColorManagement::Off => f.write_str("off"),
+            ColorManagement::Force => f.write_str("force"),
+        }
+    }
+}
+
+/// Converts `img` from the colour space described by `icc` to sRGB, in place.
+pub fn convert_to_srgb(img: &mut RgbaImage, icc: &[u8]) -> Result<(), String> {
+    let source = ColorProfile::new_from_slice(icc).map_err(|e| format!("invalid ICC profile: {}", e))?;
+    let transform = source
+        .create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, TransformOptions::default())
+        .map_err(|e| format!("unsupported ICC profile: {}", e))?;
+
+    let mut converted = vec![0u8; img.as_raw().len()];
+    transform.transform(img.as_raw(), &mut converted).map_err(|e| e.to_string())?;
+    let dst: &mut [u8] = img;
+    dst.copy_from_slice(&converted);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_display_p3_to_srgb() {
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+
+        // A saturated P3 red is more saturated still in sRGB: red rises and green falls
+        let mut img = RgbaImage::from_pixel(2, 2, Rgba([200, 40, 40, 255]));
+        convert_to_srgb(&mut img, &p3).unwrap();
+        let p = img.get_pixel(0, 0);
+        assert!(p[0] > 200 && p[1] < 40, "{:?}", p);
+        assert_eq!(p[3], 255);
+
+        let srgb = ColorProfile::new_srgb().encode().unwrap();
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([10, 120, 230, 128]));
+        convert_to_srgb(&mut img, &srgb).unwrap();
+        let p = img.get_pixel(0, 0);
+        assert!(p[0].abs_diff(10) <= 1 && p[1].abs_diff(120) <= 1 && p[2].abs_diff(230) <= 1);
+
+        assert!(convert_to_srgb(&mut img, b"not a profile").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//! Colour ramps for visualizing scalar maps.
+
+use image::Rgba;
+
+// Blue -> cyan -> green -> yellow -> red
+const HEAT_STOPS: [[f32; 3]; 5] = [
+    [0.0, 0.0, 255.0],
+    [0.0, 255.0, 255.0],
+    [0.0, 255.0, 0.0],
+    [255.0, 255.0, 0.0],
+    [255.0, 0.0, 0.0],
+];
+
+/// Maps `t` in 0.0..=1.0 onto a blue-to-red heat ramp.
+pub fn heat(t: f32) -> Rgba<u8> {
+    let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+    let scaled = t * (HEAT_STOPS.len() - 1) as f32;
+    let i = (scaled.floor() as usize).min(HEAT_STOPS.len() - 2);
+    let f = scaled - i as f32;
+
+    let (lo, hi) = (HEAT_STOPS[i], HEAT_STOPS[i + 1]);
+    let mix = |c: usize| (lo[c] + (hi[c] - lo[c]) * f).round() as u8;
+    Rgba([mix(0), mix(1), mix(2), 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heat_endpoints() {
+        assert_eq!(heat(0.0), Rgba([0, 0, 255, 255]));
+        assert_eq!(heat(1.0), Rgba([255, 0, 0, 255]));
+        assert_eq!(heat(0.5), Rgba([0, 255, 0, 255]));
+        assert_eq!(heat(f32::NAN), heat(0.0));
+    }
+}
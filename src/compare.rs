@@ -1,21 +1,87 @@
-use anyhow::Result;
-use image::{GenericImageView, ImageBuffer, Rgba};
-use image_compare::Algorithm;
-use serde::Serialize;
+use image::{DynamicImage, ImageBuffer, Pixel, Rgba, Rgba32FImage, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use lab::Lab;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::str::FromStr;
+use crate::align;
+use crate::cluster::{self, DiffRegion};
+use crate::color::{self, ColorManagement};
+use crate::error::{open_image, open_image_with_profile, DiffError, Result};
+use crate::metric::{rgb2y, PixelMetric};
+use crate::options::{CompareMode, CompareOptions};
+use crate::pixelmatch;
+use crate::precision::{self, Precision, Rgba16Image};
+use crate::render::{self, DiffStyle};
+use crate::rows::{self, RowBand, RowBandKind};
+use crate::size;
+use crate::ssim::{self, SsimMap, SsimRegion};
+
+/// Colour of differing pixels in the diff overlay.
+pub const DIFF_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+/// Colour of anti-aliased pixels in the diff overlay.
+pub const AA_COLOR: Rgba<u8> = Rgba([255, 255, 0, 255]);
+/// Colour of canvas areas outside one of the images when padding mismatched sizes.
+pub const PAD_COLOR: Rgba<u8> = Rgba([0, 255, 255, 255]);
+/// Colour of the outlines drawn around diff regions.
+pub const BOX_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+/// Tint of rows present only in the second image when aligning rows.
+pub const INSERTED_COLOR: Rgba<u8> = Rgba([0, 200, 0, 255]);
+/// Tint of rows present only in the first image when aligning rows.
+pub const REMOVED_COLOR: Rgba<u8> = Rgba([220, 0, 0, 255]);
 
 #[derive(Serialize)]
 pub struct DiffResult {
     pub score: f64,
-    pub ssim_score: f64,
+    /// `None` when SSIM is disabled or could not be computed
+    pub ssim_score: Option<f64>,
+    /// Why SSIM could not be computed, if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssim_error: Option<String>,
     pub diff_pixels: u64,
+    /// Pixels over the threshold that were attributed to anti-aliasing
+    pub aa_pixels: u64,
     pub total_pixels: u64,
+    /// Tiles of the SSIM map whose minimum SSIM is below 1.0
+    pub ssim_regions: Vec<SsimRegion>,
+    /// Original (width, height) of the first image
+    pub dimensions_a: (u32, u32),
+    /// Original (width, height) of the second image
+    pub dimensions_b: (u32, u32),
+    /// Whether the inputs had different dimensions
+    pub size_mismatch: bool,
+    /// Connected clusters of differing pixels
+    pub regions: Vec<DiffRegion>,
+    /// Rows that did not match exactly, when row alignment is enabled
+    pub row_bands: Vec<RowBand>,
+    /// Whether an embedded ICC profile was applied to either input
+    pub color_converted: bool,
+    /// Whether `CompareOptions::max_diff_pixels` was exceeded, so scanning may have stopped early.
+    /// Rows already being scanned on other threads finish first, so pixel counts and regions are
+    /// then a lower bound that can vary between runs; SSIM is skipped
+    pub truncated: bool,
+    /// Detected translation `(dx, dy)` of the second image, when alignment is enabled
+    pub offset: Option<(i32, i32)>,
     #[serde(skip)]
-    pub diff_image: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    pub diff_image: Option<RgbaImage>,
+    /// Full-depth diff image, set instead of only `diff_image` when comparing at native precision
+    #[serde(skip)]
+    pub diff_image_16: Option<Rgba16Image>,
+    #[serde(skip)]
+    pub ssim_map: Option<SsimMap>,
+    /// Both images on the shared canvas they were compared on, if requested
+    #[serde(skip)]
+    pub canvas: Option<(RgbaImage, RgbaImage)>,
+}
+
+impl DiffResult {
+    /// Whether more pixels differ than `CompareOptions::max_diff_pixels` allows.
+    pub fn fails(&self, options: &CompareOptions) -> bool {
+        self.diff_pixels > options.max_diff_pixels.unwrap_or(0)
+    }
 }
 
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Region {
     pub x: u32,
     pub y: u32,
@@ -29,192 +95,539 @@ impl Region {
     }
 }
 
-pub fn compare_images(
-    path_a: &Path,
-    path_b: &Path,
-    threshold: f32,
-    generate_diff: bool,
-    ignore_regions: &[Region],
-    mask_path: Option<&Path>,
+impl FromStr for Region {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u32> = s.split(',')
+            .map(|p| p.parse::<u32>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if parts.len() != 4 {
+            return Err(anyhow::anyhow!("Region must be in format x,y,width,height"));
+        }
+        Ok(Region { x: parts[0], y: parts[1], width: parts[2], height: parts[3] })
+    }
+}
+
+/// Compares two image files.
+///
+/// Embedded ICC profiles are applied according to `options.color_management`, except at
+/// native precision, where channel data is compared as stored.
+pub fn compare_images(path_a: &Path, path_b: &Path, options: &CompareOptions) -> Result<DiffResult> {
+    options.validate()?;
+    if options.precision == Precision::Native {
+        let img_a = open_image(path_a, &options.svg)?;
+        let img_b = open_image(path_b, &options.svg)?;
+        return compare_buffers_native(&img_a.to_rgba32f(), &img_b.to_rgba32f(), options);
+    }
+
+    let (img_a, converted_a) = load_rgba(path_a, options)?;
+    let (img_b, converted_b) = load_rgba(path_b, options)?;
+
+    let mut res = compare_buffers(&img_a, &img_b, options)?;
+    res.color_converted = converted_a || converted_b;
+    Ok(res)
+}
+
+/// Loads an image as sRGB RGBA, returning whether an ICC profile was applied.
+fn load_rgba(path: &Path, options: &CompareOptions) -> Result<(RgbaImage, bool)> {
+    let color_management = options.color_management;
+    if color_management == ColorManagement::Off {
+        return Ok((open_image(path, &options.svg)?.to_rgba8(), false));
+    }
+
+    let (img, icc) = open_image_with_profile(path, &options.svg)?;
+    let mut rgba = img.to_rgba8();
+    let Some(icc) = icc else {
+        return Ok((rgba, false));
+    };
+
+    match color::convert_to_srgb(&mut rgba, &icc) {
+        Ok(()) => Ok((rgba, true)),
+        Err(reason) if color_management == ColorManagement::Force => {
+            Err(DiffError::ColorProfile { path: path.to_path_buf(), reason })
+        }
+        Err(_) => Ok((rgba, false)),
+    }
+}
+
+/// Compares two in-memory RGBA images.
+///
+/// Images of different sizes are handled according to `options.size_mismatch`.
+pub fn compare_buffers(img_a: &RgbaImage, img_b: &RgbaImage, options: &CompareOptions) -> Result<DiffResult> {
+    compare_buffers_with_metric(img_a, img_b, options, options.metric.pixel_metric())
+}
+
+/// Like [`compare_buffers`], but with a caller-supplied metric instead of `options.metric`.
+pub fn compare_buffers_with_metric(
+    img_a: &RgbaImage,
+    img_b: &RgbaImage,
+    options: &CompareOptions,
+    metric: &dyn PixelMetric,
 ) -> Result<DiffResult> {
-    let img_a = image::open(path_a)?;
-    let img_b = image::open(path_b)?;
+    options.validate()?;
+    let mask_img = match &options.mask {
+        Some(path) => Some(open_mask(path)?),
+        None => None,
+    };
 
-    let (width_a, height_a) = img_a.dimensions();
-    let (width_b, height_b) = img_b.dimensions();
+    compare_rgba(img_a, img_b, options, metric, mask_img.as_ref())
+}
 
-    let max_width = width_a.max(width_b);
-    let max_height = height_a.max(height_b);
+/// Compares two float RGBA images at native precision, using `options.tolerance`.
+///
+/// 16-bit data should be normalised to 0.0-1.0, as `DynamicImage::to_rgba32f` does. Both images
+/// must have the same dimensions, and settings that only apply to 8-bit comparisons are rejected
+/// (see [`CompareOptions::validate`]). SSIM is computed on 8-bit copies.
+pub fn compare_buffers_native(
+    img_a: &Rgba32FImage,
+    img_b: &Rgba32FImage,
+    options: &CompareOptions,
+) -> Result<DiffResult> {
+    options.validate()?;
+    let dimensions_a = img_a.dimensions();
+    let dimensions_b = img_b.dimensions();
+    if dimensions_a != dimensions_b {
+        return Err(DiffError::DimensionMismatch { a: dimensions_a, b: dimensions_b });
+    }
 
-    let mask_img = if let Some(path) = mask_path {
-        Some(image::open(path)?.to_rgba8())
-    } else {
-        None
+    let mask_img = match &options.mask {
+        Some(path) => Some(open_mask(path)?),
+        None => None,
     };
 
-    // For SSIM, we need identical dimensions.
-    let mut rgba_a = img_a.to_rgba8();
-    let mut rgba_b = img_b.to_rgba8();
+    let (width, height) = dimensions_a;
+    let mut diff_buffer = options.generate_diff.then(|| Rgba16Image::new(width, height));
+    let pass = precision::diff(
+        img_a,
+        img_b,
+        options.tolerance,
+        |x, y| is_ignored(x, y, &options.ignore_regions, mask_img.as_ref()),
+        diff_buffer.as_mut(),
+        options.max_diff_pixels,
+    );
+
+    let regions = cluster::diff_regions(&pass);
+    if let (Some(buffer), Some(padding)) = (diff_buffer.as_mut(), options.boxes) {
+        let color = Rgba(BOX_COLOR.0.map(|c| c as u16 * 257));
+        for r in &regions {
+            let b = render::padded_box(&r.region, padding, width, height);
+            render::draw_box(buffer, &b, render::box_thickness(width, height), color);
+        }
+    }
+
+    let rgba_a = DynamicImage::ImageRgba32F(img_a.clone()).to_rgba8();
+    let rgba_b = DynamicImage::ImageRgba32F(img_b.clone()).to_rgba8();
+    let ssim = SsimOutcome::compute(&rgba_a, &rgba_b, options, pass.truncated);
+
+    Ok(DiffResult {
+        score: 1.0 - (pass.diff_pixels as f64 / pass.distances.len() as f64),
+        ssim_score: ssim.score,
+        ssim_error: ssim.error,
+        diff_pixels: pass.diff_pixels,
+        aa_pixels: 0,
+        total_pixels: pass.distances.len() as u64,
+        ssim_regions: ssim.regions,
+        dimensions_a,
+        dimensions_b,
+        size_mismatch: false,
+        regions,
+        color_converted: false,
+        truncated: pass.truncated,
+        row_bands: Vec::new(),
+        offset: None,
+        diff_image: diff_buffer.as_ref().map(|b| DynamicImage::ImageRgba16(b.clone()).to_rgba8()),
+        diff_image_16: diff_buffer,
+        ssim_map: ssim.map,
+        canvas: options.generate_canvas.then_some((rgba_a, rgba_b)),
+    })
+}
 
-    if width_a != max_width || height_a != max_height {
-        let mut new_a = ImageBuffer::new(max_width, max_height);
-        image::imageops::overlay(&mut new_a, &rgba_a, 0, 0);
-        rgba_a = new_a;
+fn open_mask(path: &Path) -> Result<RgbaImage> {
+    match image::open(path) {
+        Ok(img) => Ok(img.to_rgba8()),
+        Err(image::ImageError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(DiffError::MissingFile(path.to_path_buf()))
+        }
+        Err(e) => Err(DiffError::InvalidMask { path: path.to_path_buf(), reason: e.to_string() }),
     }
+}
+
+fn compare_rgba(
+    img_a: &RgbaImage,
+    img_b: &RgbaImage,
+    options: &CompareOptions,
+    metric: &dyn PixelMetric,
+    mask_img: Option<&RgbaImage>,
+) -> Result<DiffResult> {
+    let threshold = options.threshold;
+
+    let dimensions_a = img_a.dimensions();
+    let dimensions_b = img_b.dimensions();
 
-    if width_b != max_width || height_b != max_height {
-        let mut new_b = ImageBuffer::new(max_width, max_height);
-        image::imageops::overlay(&mut new_b, &rgba_b, 0, 0);
-        rgba_b = new_b;
+    // For SSIM, we need identical dimensions.
+    let (mut canvas, alignment) = if options.align_rows {
+        let (canvas, alignment) = rows::align(img_a, img_b, options.size_mismatch, options.anchor)?;
+        (canvas, Some(alignment))
+    } else {
+        (size::fit(img_a, img_b, options.size_mismatch, options.anchor)?, None)
+    };
+
+    let offset = options.align.map(|max_offset| align::find_offset(&canvas.a, &canvas.b, max_offset));
+    if let Some(offset) = offset {
+        align::apply_offset(&mut canvas, offset);
     }
 
-    let mut diff_pixels = 0u64;
-    let total_pixels = (max_width as u64) * (max_height as u64);
+    options.alpha.apply(&mut canvas);
+
+    let (rgba_a, rgba_b) = (&canvas.a, &canvas.b);
+    let (width, height) = rgba_a.dimensions();
+
+    let total_pixels = (width as u64) * (height as u64);
 
-    let mut diff_buffer = if generate_diff {
-        Some(ImageBuffer::new(max_width, max_height))
+    let mut diff_buffer = if options.generate_diff {
+        Some(ImageBuffer::new(width, height))
     } else {
         None
     };
 
-    for y in 0..max_height {
-        for x in 0..max_width {
-            let mut is_ignored = ignore_regions.iter().any(|r| r.contains(x, y));
-            
-            if !is_ignored {
-                if let Some(ref mask) = mask_img {
-                    if x < mask.width() && y < mask.height() {
-                        let mask_pixel = mask.get_pixel(x, y);
-                        // Ignore if mask pixel is black or has low alpha
-                        if (mask_pixel[0] == 0 && mask_pixel[1] == 0 && mask_pixel[2] == 0) || mask_pixel[3] < 128 {
-                            is_ignored = true;
-                        }
-                    }
-                }
-            }
+    // Ignore regions and masks are in the coordinates of the first image, so canvas pixels are
+    // mapped back through padding, cropping and row alignment; pixels outside it are compared
+    let is_ignored = |x: u32, y: u32| {
+        let Some((source_x, source_y)) = canvas.source_a(x, y) else {
+            return false;
+        };
+        // Row alignment moves rows of the first image, so its source row comes from the alignment
+        let source_y = alignment.as_ref().map_or(Some(source_y), |al| al.rows[y as usize].0);
+        source_y.is_some_and(|y| is_ignored(source_x, y, &options.ignore_regions, mask_img))
+    };
 
-            let pixel_a = rgba_a.get_pixel(x, y);
-            let pixel_b = rgba_b.get_pixel(x, y);
+    let pass = match options.mode {
+        CompareMode::Standard => standard_diff(rgba_a, rgba_b, options, metric, is_ignored, diff_buffer.as_mut()),
+        CompareMode::Pixelmatch => pixelmatch::diff(
+            rgba_a,
+            rgba_b,
+            threshold,
+            &options.pixelmatch,
+            is_ignored,
+            diff_buffer.as_mut(),
+            options.max_diff_pixels,
+        ),
+    };
 
-            let dist = if is_ignored {
-                0.0 // Treat as identical
-            } else {
-                let d = color_distance(pixel_a, pixel_b);
-                // Simple anti-aliasing check: if difference is small but > threshold, check neighbors
-                if d > (threshold as f64) && d < (threshold as f64 * 1.5) {
-                    if is_antialiased(x, y, max_width, max_height, &rgba_a, &rgba_b) {
-                        0.0
-                    } else {
-                        d
-                    }
-                } else {
-                    d
-                }
-            };
-            
-            let is_different = dist > (threshold as f64);
-
-            if is_different {
-                diff_pixels += 1;
-                if let Some(ref mut buffer) = diff_buffer {
-                    buffer.put_pixel(x, y, Rgba([255, 0, 255, 255]));
+    if let Some(buffer) = diff_buffer.as_mut() {
+        if canvas.has_padding() {
+            for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                if canvas.is_padding(x, y) {
+                    *pixel = PAD_COLOR;
                 }
-            } else if let Some(ref mut buffer) = diff_buffer {
-                let factor = if is_ignored { 0.02 } else { 0.1 };
-                let r = (pixel_a[0] as f32 * factor) as u8;
-                let g = (pixel_a[1] as f32 * factor) as u8;
-                let b = (pixel_a[2] as f32 * factor) as u8;
-                buffer.put_pixel(x, y, Rgba([r, g, b, 255]));
             }
         }
     }
 
+    let DiffPass { diff_pixels, aa_pixels, .. } = pass;
     let score = 1.0 - (diff_pixels as f64 / total_pixels as f64);
+    let regions = cluster::diff_regions(&pass);
 
-    // Calculate SSIM using RGB
-    let rgb_a = image::DynamicImage::ImageRgba8(rgba_a).to_rgb8();
-    let rgb_b = image::DynamicImage::ImageRgba8(rgba_b).to_rgb8();
-    let ssim_score = image_compare::rgb_similarity_structure(&Algorithm::MSSIMSimple, &rgb_a, &rgb_b).unwrap().score;
+    let diff_buffer = diff_buffer.map(|overlay| {
+        let mut composed = render::compose(options.diff_style, rgba_a, rgba_b, overlay, &pass);
+        // Tinted after composing so every layout shows the bands, not only those with an overlay panel
+        if let Some(alignment) = &alignment {
+            paint_row_bands(&mut composed, &alignment.bands, rgba_a, rgba_b);
+        }
+        if let Some(padding) = options.boxes {
+            let thickness = render::box_thickness(width, height);
+            for panel in 0..options.diff_style.panels() {
+                for r in &regions {
+                    let mut b = render::padded_box(&r.region, padding, width, height);
+                    b.x += panel * width;
+                    render::draw_box(&mut composed, &b, thickness, BOX_COLOR);
+                }
+            }
+        }
+        if options.legend && options.diff_style == DiffStyle::Heatmap {
+            composed = render::append_legend(&composed, threshold);
+        }
+        composed
+    });
+
+    let ssim = SsimOutcome::compute(rgba_a, rgba_b, options, pass.truncated);
 
     Ok(DiffResult {
         score,
-        ssim_score,
+        ssim_score: ssim.score,
+        ssim_error: ssim.error,
         diff_pixels,
+        aa_pixels,
         total_pixels,
+        ssim_regions: ssim.regions,
+        dimensions_a,
+        dimensions_b,
+        size_mismatch: dimensions_a != dimensions_b,
+        regions,
+        color_converted: false,
+        truncated: pass.truncated,
+        row_bands: alignment.map(|al| al.bands).unwrap_or_default(),
+        offset,
         diff_image: diff_buffer,
+        diff_image_16: None,
+        ssim_map: ssim.map,
+        canvas: options.generate_canvas.then(|| (canvas.a.into_owned(), canvas.b.into_owned())),
     })
 }
 
+/// SSIM fields of a `DiffResult`, empty when SSIM is disabled or the pixel scan was truncated.
+#[derive(Default)]
+struct SsimOutcome {
+    score: Option<f64>,
+    error: Option<String>,
+    regions: Vec<SsimRegion>,
+    map: Option<SsimMap>,
+}
+
+impl SsimOutcome {
+    fn compute(rgba_a: &RgbaImage, rgba_b: &RgbaImage, options: &CompareOptions, truncated: bool) -> Self {
+        // A truncated scan has already failed, so SSIM would only cost time
+        if !options.ssim.enabled || truncated {
+            return SsimOutcome::default();
+        }
+        match ssim::structural_similarity(rgba_a, rgba_b, &options.ssim) {
+            Ok((score, map)) => SsimOutcome {
+                score: Some(score),
+                error: None,
+                regions: ssim::low_ssim_regions(&map, options.ssim.tile_size),
+                map: options.ssim.generate_map.then_some(map),
+            },
+            Err(e) => SsimOutcome { error: Some(e), ..SsimOutcome::default() },
+        }
+    }
+}
 
-fn color_distance(p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
-    // Convert RGBA to Lab for perceptual distance
-    let lab1 = Lab::from_rgb(&[p1[0], p1[1], p1[2]]);
-    let lab2 = Lab::from_rgb(&[p2[0], p2[1], p2[2]]);
-
-    // Calculate DeltaE 2000
-    // We normalize by alpha difference roughly since DeltaE is color-only
-    let color_diff = delta_e::DE2000::new(lab1, lab2) as f64;
-    
-    // Scale alpha difference (0-255 -> 0-100 to match Lab scale roughly)
-    let alpha_diff = (p1[3] as f64 - p2[3] as f64).abs() / 2.55;
-
-    // Combine: weighted average or max.
-    // CIEDE2000 > 2.3 is usually "just noticeable difference" (JND)
-    // We treat > 10.0 as a significant color shift.
-    // Let's normalize to roughly 0.0-1.0 range for our threshold logic by dividing by 100.0
-    // But our tool expects threshold 0.1 (10%).
-    
-    (color_diff + alpha_diff) / 100.0
+/// Tints rows that exist in only one image in every panel, keeping their content faintly visible.
+fn paint_row_bands(buffer: &mut RgbaImage, bands: &[RowBand], rgba_a: &RgbaImage, rgba_b: &RgbaImage) {
+    let width = rgba_a.width();
+    for band in bands {
+        let (source, color) = match band.kind {
+            RowBandKind::Inserted => (rgba_b, INSERTED_COLOR),
+            RowBandKind::Removed => (rgba_a, REMOVED_COLOR),
+            RowBandKind::Changed => continue,
+        };
+        for y in band.y..band.y + band.height {
+            for x in 0..buffer.width() {
+                let p = source.get_pixel(x % width, y);
+                let luma = rgb2y(p[0] as f64, p[1] as f64, p[2] as f64) * p[3] as f64 / 255.0;
+                let mix = |c: u8| (c as f64 * 0.7 + luma * 0.3) as u8;
+                buffer.put_pixel(x, y, Rgba([mix(color[0]), mix(color[1]), mix(color[2]), 255]));
+            }
+        }
+    }
 }
 
-fn is_antialiased(
-    x: u32,
-    y: u32,
-    width: u32,
-    height: u32,
-    img_a: &ImageBuffer<Rgba<u8>, Vec<u8>>,
-    img_b: &ImageBuffer<Rgba<u8>, Vec<u8>>
-) -> bool {
-    // Check if pixel value is roughly an average of its neighbors in either image
-    // This is a heuristic: if a pixel is different but its value exists in the neighbor
-    // set of the other image, it's likely a sub-pixel shift.
-    
-    let neighbors = [
-        (x.saturating_sub(1), y),
-        (x + 1, y),
-        (x, y.saturating_sub(1)),
-        (x, y + 1),
-    ];
-    
-    let _p_a = img_a.get_pixel(x, y);
-    let p_b = img_b.get_pixel(x, y);
-    
-    // Check if B's pixel exists in A's neighbors (shift)
-    for (nx, ny) in neighbors {
-        if nx < width && ny < height {
-            let neighbor_a = img_a.get_pixel(nx, ny);
-            if color_distance(p_b, neighbor_a) < 0.05 {
+fn is_ignored(x: u32, y: u32, ignore_regions: &[Region], mask_img: Option<&RgbaImage>) -> bool {
+    if ignore_regions.iter().any(|r| r.contains(x, y)) {
+        return true;
+    }
+
+    if let Some(mask) = mask_img {
+        if x < mask.width() && y < mask.height() {
+            let mask_pixel = mask.get_pixel(x, y);
+            // Ignore if mask pixel is black or has low alpha
+            if (mask_pixel[0] == 0 && mask_pixel[1] == 0 && mask_pixel[2] == 0) || mask_pixel[3] < 128 {
                 return true;
             }
         }
     }
-    
+
     false
 }
 
+/// Per-pixel outcome of a diff loop over the canvas.
+pub(crate) struct DiffPass {
+    pub width: u32,
+    pub height: u32,
+    pub diff_pixels: u64,
+    pub aa_pixels: u64,
+    /// Distance of every pixel in row-major order, including those under the threshold
+    pub distances: Vec<f32>,
+    /// Whether each pixel was counted as different
+    pub different: Vec<bool>,
+    /// Whether the diff limit was exceeded, so rows may have been left unscanned
+    pub truncated: bool,
+}
+
+impl DiffPass {
+    pub fn new(width: u32, height: u32) -> Self {
+        DiffPass {
+            width,
+            height,
+            diff_pixels: 0,
+            aa_pixels: 0,
+            distances: vec![0.0; width as usize * height as usize],
+            different: vec![false; width as usize * height as usize],
+            truncated: false,
+        }
+    }
+
+    #[cfg(test)]
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    #[cfg(test)]
+    pub fn record(&mut self, x: u32, y: u32, distance: f32) {
+        let i = self.index(x, y);
+        self.distances[i] = distance;
+    }
+
+    #[cfg(test)]
+    pub fn mark_different(&mut self, x: u32, y: u32) {
+        let i = self.index(x, y);
+        self.diff_pixels += 1;
+        self.different[i] = true;
+    }
+}
+
+/// How a pixel was classified by a diff loop.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PixelOutcome {
+    Same,
+    AntiAliased,
+    Different,
+}
+
+/// Runs a diff loop over all pixels, one row per rayon task, on the current thread pool.
+///
+/// `classify(x, y, identical_row)` returns the pixel's distance, outcome and diff image colour.
+/// `identical_row` is set when the row's bytes match in both images, so callers can skip
+/// the metric. Once more than `limit` pixels differ, rows not yet started are skipped while rows
+/// in flight finish, and the pass is marked truncated.
+pub(crate) fn par_rows<P, Q>(
+    img_a: &ImageBuffer<P, Vec<P::Subpixel>>,
+    img_b: &ImageBuffer<P, Vec<P::Subpixel>>,
+    diff_buffer: Option<&mut ImageBuffer<Q, Vec<Q::Subpixel>>>,
+    limit: Option<u64>,
+    classify: impl Fn(u32, u32, bool) -> (f32, PixelOutcome, Q) + Sync,
+) -> DiffPass
+where
+    P: Pixel + Sync,
+    P::Subpixel: Sync,
+    Q: Pixel,
+    Q::Subpixel: Send,
+{
+    let (width, height) = img_a.dimensions();
+    let mut pass = DiffPass::new(width, height);
+    if width == 0 || height == 0 {
+        return pass;
+    }
+
+    let row_len = width as usize;
+    let stride = row_len * P::CHANNEL_COUNT as usize;
+    let out_stride = row_len * Q::CHANNEL_COUNT as usize;
+    let out_rows: Vec<Option<&mut [Q::Subpixel]>> = match diff_buffer {
+        Some(buffer) => buffer.chunks_mut(out_stride).map(Some).collect(),
+        None => (0..height).map(|_| None).collect(),
+    };
+
+    let found = AtomicU64::new(0);
+
+    let (diff_pixels, aa_pixels) = pass
+        .distances
+        .par_chunks_mut(row_len)
+        .zip(pass.different.par_chunks_mut(row_len))
+        .zip(out_rows)
+        .enumerate()
+        .map(|(y, ((distances, different), mut out))| {
+            if limit.is_some_and(|limit| found.load(Ordering::Relaxed) > limit) {
+                return (0, 0);
+            }
+
+            let row = y * stride..(y + 1) * stride;
+            let identical_row = img_a.as_raw()[row.clone()] == img_b.as_raw()[row];
+            let (mut diff_pixels, mut aa_pixels) = (0u64, 0u64);
+
+            for x in 0..row_len {
+                let (distance, outcome, color) = classify(x as u32, y as u32, identical_row);
+                distances[x] = distance;
+                match outcome {
+                    PixelOutcome::Different => {
+                        different[x] = true;
+                        diff_pixels += 1;
+                    }
+                    PixelOutcome::AntiAliased => aa_pixels += 1,
+                    PixelOutcome::Same => {}
+                }
+                if let Some(out) = out.as_deref_mut() {
+                    let channels = Q::CHANNEL_COUNT as usize;
+                    out[x * channels..(x + 1) * channels].copy_from_slice(color.channels());
+                }
+            }
+
+            found.fetch_add(diff_pixels, Ordering::Relaxed);
+            (diff_pixels, aa_pixels)
+        })
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    pass.diff_pixels = diff_pixels;
+    pass.aa_pixels = aa_pixels;
+    // Whether any row was actually skipped depends on scheduling, so exceeding the limit is what
+    // marks the pass; the scan cannot end under the limit once the full count exceeds it
+    pass.truncated = limit.is_some_and(|limit| diff_pixels > limit);
+    pass
+}
+
+fn standard_diff(
+    rgba_a: &RgbaImage,
+    rgba_b: &RgbaImage,
+    options: &CompareOptions,
+    metric: &dyn PixelMetric,
+    is_ignored: impl Fn(u32, u32) -> bool + Sync,
+    diff_buffer: Option<&mut RgbaImage>,
+) -> DiffPass {
+    let threshold = options.threshold as f64;
+    let anti_aliasing = options.anti_aliasing;
+
+    par_rows(rgba_a, rgba_b, diff_buffer, options.max_diff_pixels, |x, y, identical_row| {
+        let is_ignored = is_ignored(x, y);
+        let pixel_a = rgba_a.get_pixel(x, y);
+
+        let dist = if is_ignored || identical_row {
+            0.0 // Treat as identical
+        } else {
+            metric.distance(pixel_a, rgba_b.get_pixel(x, y))
+        };
+
+        if dist > threshold {
+            if anti_aliasing.is_antialiased(rgba_a, rgba_b, (x, y), dist, threshold, metric) {
+                (dist as f32, PixelOutcome::AntiAliased, AA_COLOR)
+            } else {
+                (dist as f32, PixelOutcome::Different, DIFF_COLOR)
+            }
+        } else {
+            let factor = if is_ignored { 0.02 } else { 0.1 };
+            let dim = |c: u8| (c as f32 * factor) as u8;
+            (dist as f32, PixelOutcome::Same, Rgba([dim(pixel_a[0]), dim(pixel_a[1]), dim(pixel_a[2]), 255]))
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::alpha::AlphaMode;
+    use crate::antialias::tests::edge_pair;
+    use crate::antialias::AntiAliasing;
+    use crate::metric::Metric;
+    use crate::size::{Anchor, SizeMismatch};
 
     #[test]
     fn test_color_distance() {
+        let metric = Metric::default().pixel_metric();
         let p1 = Rgba([0, 0, 0, 255]);
         let p2 = Rgba([255, 255, 255, 255]);
         // Black vs White is approx 1.0 (100.0 / 100.0)
-        assert!((color_distance(&p1, &p2) - 1.0).abs() < 0.1);
+        assert!((metric.distance(&p1, &p2) - 1.0).abs() < 0.1);
 
         let p3 = Rgba([100, 100, 100, 255]);
-        assert_eq!(color_distance(&p3, &p3), 0.0);
+        assert_eq!(metric.distance(&p3, &p3), 0.0);
     }
 
     #[test]
@@ -227,7 +640,7 @@ mod tests {
     }
 
     #[test]
-    fn test_compare_identical() -> Result<()> {
+    fn test_compare_identical() -> anyhow::Result<()> {
         let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(10, 10);
         for p in img.pixels_mut() { *p = Rgba([100, 100, 100, 255]); }
         
@@ -236,15 +649,15 @@ mod tests {
         img.save(file_a.path())?;
         img.save(file_b.path())?;
 
-        let res = compare_images(file_a.path(), file_b.path(), 0.1, false, &[], None)?;
+        let res = compare_images(file_a.path(), file_b.path(), &CompareOptions::default())?;
         assert_eq!(res.diff_pixels, 0);
         assert_eq!(res.score, 1.0);
-        assert!(res.ssim_score > 0.99);
+        assert!(res.ssim_score.unwrap() > 0.99);
         Ok(())
     }
 
     #[test]
-    fn test_compare_different_with_ignore() -> Result<()> {
+    fn test_compare_different_with_ignore() -> anyhow::Result<()> {
         let mut img_a: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(10, 10);
         for p in img_a.pixels_mut() { *p = Rgba([100, 100, 100, 255]); }
         
@@ -257,19 +670,21 @@ mod tests {
         img_b.save(file_b.path())?;
 
         // Without ignore
-        let res1 = compare_images(file_a.path(), file_b.path(), 0.1, false, &[], None)?;
+        let res1 = compare_images(file_a.path(), file_b.path(), &CompareOptions::default())?;
         assert_eq!(res1.diff_pixels, 1);
 
         // With ignore
-        let ignore = [Region { x: 5, y: 5, width: 1, height: 1 }];
-        let res2 = compare_images(file_a.path(), file_b.path(), 0.1, false, &ignore, None)?;
+        let options = CompareOptions::builder()
+            .ignore_region(Region { x: 5, y: 5, width: 1, height: 1 })
+            .build();
+        let res2 = compare_images(file_a.path(), file_b.path(), &options)?;
         assert_eq!(res2.diff_pixels, 0);
         assert_eq!(res2.score, 1.0);
         Ok(())
     }
 
     #[test]
-    fn test_compare_with_mask() -> Result<()> {
+    fn test_compare_with_mask() -> anyhow::Result<()> {
         let mut img_a: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(10, 10);
         for p in img_a.pixels_mut() { *p = Rgba([100, 100, 100, 255]); }
         
@@ -288,8 +703,298 @@ mod tests {
         img_b.save(file_b.path())?;
         mask.save(file_mask.path())?;
 
-        let res = compare_images(file_a.path(), file_b.path(), 0.1, false, &[], Some(file_mask.path()))?;
+        let options = CompareOptions::builder()
+            .mask(Some(file_mask.path().to_path_buf()))
+            .build();
+        let res = compare_images(file_a.path(), file_b.path(), &options)?;
+        assert_eq!(res.diff_pixels, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_buffers() {
+        let mut img_a: RgbaImage = ImageBuffer::new(10, 10);
+        for p in img_a.pixels_mut() { *p = Rgba([100, 100, 100, 255]); }
+
+        let mut img_b = img_a.clone();
+        img_b.put_pixel(2, 3, Rgba([0, 0, 255, 255]));
+
+        let options = CompareOptions { generate_diff: true, ..Default::default() };
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert_eq!(res.diff_pixels, 1);
+        assert_eq!(res.total_pixels, 100);
+
+        let diff_img = res.diff_image.unwrap();
+        assert_eq!(*diff_img.get_pixel(2, 3), DIFF_COLOR);
+    }
+
+    #[test]
+    fn test_compare_counts_aa_separately() {
+        let (img_a, img_b) = edge_pair();
+
+        let options = CompareOptions::builder()
+            .anti_aliasing(AntiAliasing::Full)
+            .generate_diff(true)
+            .build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert_eq!(res.diff_pixels, 0);
+        assert_eq!(res.aa_pixels, 8);
+        assert_eq!(*res.diff_image.unwrap().get_pixel(4, 0), AA_COLOR);
+
+        let options = CompareOptions::builder().anti_aliasing(AntiAliasing::Off).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert_eq!(res.diff_pixels, 8);
+        assert_eq!(res.aa_pixels, 0);
+    }
+
+    #[test]
+    fn test_size_mismatch_pad_and_fail() {
+        let img_a = RgbaImage::from_pixel(10, 10, Rgba([100, 100, 100, 255]));
+        let img_b = RgbaImage::from_pixel(10, 11, Rgba([100, 100, 100, 255]));
+
+        let options = CompareOptions::builder().generate_diff(true).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert!(res.size_mismatch);
+        assert_eq!(res.dimensions_a, (10, 10));
+        assert_eq!(res.dimensions_b, (10, 11));
+        assert_eq!(res.diff_pixels, 10);
+        assert_eq!(*res.diff_image.unwrap().get_pixel(0, 10), PAD_COLOR);
+
+        let options = CompareOptions::builder().size_mismatch(SizeMismatch::Crop).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert_eq!(res.diff_pixels, 0);
+        assert_eq!(res.total_pixels, 100);
+
+        let options = CompareOptions::builder().size_mismatch(SizeMismatch::Fail).build();
+        assert!(matches!(
+            compare_buffers(&img_a, &img_b, &options),
+            Err(DiffError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ignore_and_mask_follow_centered_anchor() -> anyhow::Result<()> {
+        // Cropped to the centre, A's pixel (2,1) is the canvas origin
+        let gray = Rgba([100, 100, 100, 255]);
+        let mut img_a = RgbaImage::from_pixel(6, 4, gray);
+        img_a.put_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        let img_b = RgbaImage::from_pixel(2, 2, gray);
+
+        let mut mask = RgbaImage::from_pixel(6, 4, Rgba([255, 255, 255, 255]));
+        mask.put_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        let file_mask = tempfile::Builder::new().suffix(".png").tempfile()?;
+        mask.save(file_mask.path())?;
+
+        let crop = CompareOptions::builder().size_mismatch(SizeMismatch::Crop).anchor(Anchor::Center).build();
+        assert_eq!(compare_buffers(&img_a, &img_b, &crop)?.diff_pixels, 1);
+        let region = Region { x: 2, y: 1, width: 1, height: 1 };
+        let ignored = CompareOptions { ignore_regions: vec![region], ..crop.clone() };
+        assert_eq!(compare_buffers(&img_a, &img_b, &ignored)?.diff_pixels, 0);
+        let masked = CompareOptions { mask: Some(file_mask.path().to_path_buf()), ..crop };
+        assert_eq!(compare_buffers(&img_a, &img_b, &masked)?.diff_pixels, 0);
+
+        // Padded to the centre, A's pixel (0,0) lies at canvas (2,1); the padding matches B's border
+        let mut img_a = RgbaImage::from_pixel(2, 2, gray);
+        img_a.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        let mut img_b = RgbaImage::new(6, 4);
+        for (x, y) in [(2, 1), (3, 1), (2, 2), (3, 2)] {
+            img_b.put_pixel(x, y, gray);
+        }
+        let pad = CompareOptions::builder().anchor(Anchor::Center).build();
+        assert_eq!(compare_buffers(&img_a, &img_b, &pad)?.diff_pixels, 1);
+        let ignored = CompareOptions { ignore_regions: vec![Region { x: 0, y: 0, width: 1, height: 1 }], ..pad };
+        assert_eq!(compare_buffers(&img_a, &img_b, &ignored)?.diff_pixels, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_align_shifted() {
+        let img_a = RgbaImage::from_fn(80, 60, |x, y| Rgba([(x * 3) as u8, (y * 4) as u8, ((x * y) % 251) as u8, 255]));
+        let img_b = RgbaImage::from_fn(80, 60, |x, y| {
+            let (x, y) = (x.saturating_sub(2), y.saturating_sub(1));
+            Rgba([(x * 3) as u8, (y * 4) as u8, ((x * y) % 251) as u8, 255])
+        });
+
+        let res = compare_buffers(&img_a, &img_b, &CompareOptions::default()).unwrap();
+        assert_eq!(res.offset, None);
+        assert!(res.diff_pixels > 1000);
+
+        let options = CompareOptions::builder().align(Some(4)).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert_eq!(res.offset, Some((2, 1)));
+        // Only the strip uncovered by the shift differs
+        assert_eq!(res.diff_pixels, 2 * 60 + 80 - 2);
+    }
+
+    #[test]
+    fn test_align_rows_inserted_banner() {
+        let row = |y: u32| Rgba([(y * 5) as u8, 100, 50, 255]);
+        let img_a = RgbaImage::from_fn(20, 40, |_, y| row(y));
+        let img_b = RgbaImage::from_fn(20, 45, |_, y| if y < 5 { Rgba([0, 0, 255, 255]) } else { row(y - 5) });
+
+        let options = CompareOptions::builder().align_rows(true).generate_diff(true).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert_eq!(res.row_bands.len(), 1);
+        assert_eq!(res.row_bands[0].kind, RowBandKind::Inserted);
+        assert_eq!((res.row_bands[0].b_start, res.row_bands[0].b_rows), (0, 5));
+        // Only the banner differs; the content below it lines up again
+        assert_eq!(res.diff_pixels, 5 * 20);
+        assert_eq!(res.diff_image.unwrap().dimensions(), (20, 45));
+
+        // Layouts without an overlay panel show the band too
+        let options = CompareOptions::builder().align_rows(true).generate_diff(true).diff_style(DiffStyle::SideBySide);
+        let diff = compare_buffers(&img_a, &img_b, &options.build()).unwrap().diff_image.unwrap();
+        for x in [0, 20] {
+            let p = diff.get_pixel(x, 2);
+            assert!(p[1] > p[0] && p[1] > p[2], "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_boxes_around_regions() {
+        let img_a = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+        let mut img_b = img_a.clone();
+        img_b.put_pixel(10, 10, Rgba([0, 0, 0, 255]));
+
+        let options = CompareOptions::builder().generate_diff(true).boxes(Some(3)).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        let diff = res.diff_image.unwrap();
+        assert_eq!(*diff.get_pixel(10, 10), DIFF_COLOR);
+        assert_eq!(*diff.get_pixel(7, 7), BOX_COLOR);
+        assert_eq!(*diff.get_pixel(13, 10), BOX_COLOR);
+        assert_ne!(*diff.get_pixel(9, 9), BOX_COLOR);
+    }
+
+    #[test]
+    fn test_alpha_modes() {
+        let img_a = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 0]));
+        let img_b = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 0]));
+
+        let res = compare_buffers(&img_a, &img_b, &CompareOptions::default()).unwrap();
+        assert_eq!(res.diff_pixels, 16);
+
+        for alpha in [AlphaMode::Premultiply, AlphaMode::Composite(crate::options::Color([0, 0, 0]))] {
+            let options = CompareOptions::builder().alpha(alpha).build();
+            assert_eq!(compare_buffers(&img_a, &img_b, &options).unwrap().diff_pixels, 0);
+        }
+    }
+
+    #[test]
+    fn test_color_managed_files() -> anyhow::Result<()> {
+        use image::codecs::png::PngEncoder;
+        use image::{ExtendedColorType, ImageEncoder};
+
+        let dir = tempfile::tempdir()?;
+        let srgb = RgbaImage::from_pixel(4, 4, Rgba([200, 40, 40, 255]));
+        let path_a = dir.path().join("srgb.png");
+        srgb.save(&path_a)?;
+
+        // The same raw values tagged as Display P3 describe a more saturated colour
+        let path_b = dir.path().join("p3.png");
+        let mut encoder = PngEncoder::new(std::fs::File::create(&path_b)?);
+        encoder.set_icc_profile(moxcms::ColorProfile::new_display_p3().encode()?)?;
+        encoder.write_image(srgb.as_raw(), 4, 4, ExtendedColorType::Rgba8)?;
+
+        let res = compare_images(&path_a, &path_b, &CompareOptions::builder().threshold(0.0).build())?;
+        assert!(res.color_converted);
+        assert_eq!(res.diff_pixels, 16);
+
+        let options = CompareOptions::builder().threshold(0.0).color_management(ColorManagement::Off).build();
+        let res = compare_images(&path_a, &path_b, &options)?;
+        assert!(!res.color_converted);
         assert_eq!(res.diff_pixels, 0);
         Ok(())
     }
+
+    #[test]
+    fn test_native_precision_16bit() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path_a = dir.path().join("a.png");
+        let path_b = dir.path().join("b.png");
+        let mut img = Rgba16Image::from_pixel(4, 4, Rgba([30000, 30000, 30000, 65535]));
+        img.save(&path_a)?;
+        // One 16-bit step vanishes when quantised to 8 bits
+        img.put_pixel(1, 2, Rgba([30001, 30000, 30000, 65535]));
+        img.save(&path_b)?;
+
+        let options = CompareOptions::builder().threshold(0.0).generate_diff(true).build();
+        assert_eq!(compare_images(&path_a, &path_b, &options)?.diff_pixels, 0);
+
+        let native = || CompareOptions::builder().precision(Precision::Native).size_mismatch(SizeMismatch::Fail);
+        let options = native().generate_diff(true).build();
+        let res = compare_images(&path_a, &path_b, &options)?;
+        assert_eq!(res.diff_pixels, 1);
+        assert_eq!(res.regions[0].region.x, 1);
+        assert_eq!(res.diff_image_16.unwrap().get_pixel(1, 2).0, [65535, 0, 65535, 65535]);
+
+        let tolerance = precision::Tolerance { absolute: 1e-4, relative: 0.0 };
+        let options = native().tolerance(tolerance).build();
+        assert_eq!(compare_images(&path_a, &path_b, &options)?.diff_pixels, 0);
+
+        // Settings native precision cannot honour are rejected, not ignored
+        let options = native().mode(CompareMode::Pixelmatch).anti_aliasing(AntiAliasing::Full).build();
+        let err = compare_images(&path_a, &path_b, &options).err().unwrap();
+        let message = "Invalid options: mode, anti_aliasing settings cannot be used with native precision";
+        assert_eq!(err.to_string(), message);
+        let float = DynamicImage::ImageRgba16(img).to_rgba32f();
+        let options = native().metric(Metric::Exact).color_management(ColorManagement::Force).build();
+        assert!(matches!(compare_buffers_native(&float, &float, &options), Err(DiffError::InvalidOptions(_))));
+        let options = CompareOptions::builder().precision(Precision::Native).build();
+        assert!(matches!(compare_buffers_native(&float, &float, &options), Err(DiffError::InvalidOptions(_))));
+        let options = CompareOptions::builder().tolerance(tolerance).build();
+        assert_eq!(compare_images(&path_a, &path_b, &options).err().unwrap().kind(), "invalid_options");
+        Ok(())
+    }
+
+    #[test]
+    fn test_identical_rows_skip_metric() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Counting(AtomicUsize);
+        impl PixelMetric for Counting {
+            fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                if p1 == p2 { 0.0 } else { 1.0 }
+            }
+        }
+
+        let img_a = RgbaImage::from_pixel(16, 64, Rgba([40, 40, 40, 255]));
+        let mut img_b = img_a.clone();
+        img_b.put_pixel(3, 37, Rgba([255, 255, 255, 255]));
+
+        let metric = Counting(AtomicUsize::new(0));
+        let options = CompareOptions::builder().generate_diff(true).build();
+        let res = compare_buffers_with_metric(&img_a, &img_b, &options, &metric).unwrap();
+
+        // Only the changed row reaches the metric
+        assert_eq!(metric.0.load(Ordering::Relaxed), 16);
+        assert_eq!(res.diff_pixels, 1);
+        assert_eq!(*res.diff_image.unwrap().get_pixel(3, 37), DIFF_COLOR);
+    }
+
+    #[test]
+    fn test_max_diff_pixels_truncates() {
+        let img_a = RgbaImage::from_pixel(16, 256, Rgba([0, 0, 0, 255]));
+        let img_b = RgbaImage::from_pixel(16, 256, Rgba([255, 255, 255, 255]));
+
+        let options = CompareOptions::builder().max_diff_pixels(Some(0)).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert!(res.truncated && res.fails(&options));
+        assert!(res.diff_pixels > 0 && res.diff_pixels < res.total_pixels);
+        assert_eq!(res.ssim_score, None);
+
+        // Exceeding the limit marks the result even when no row was left to skip
+        let mut img_c = img_a.clone();
+        img_c.put_pixel(5, 255, Rgba([255, 255, 255, 255]));
+        let res = compare_buffers(&img_a, &img_c, &options).unwrap();
+        assert!(res.truncated && res.fails(&options));
+        assert_eq!(res.ssim_score, None);
+
+        // A limit above the actual count scans everything
+        let options = CompareOptions::builder().max_diff_pixels(Some(16 * 256)).build();
+        let res = compare_buffers(&img_a, &img_b, &options).unwrap();
+        assert!(!res.truncated && !res.fails(&options));
+        assert_eq!(res.diff_pixels, res.total_pixels);
+        assert!(res.ssim_score.is_some());
+    }
 }
@@ -1,36 +1,86 @@
-use crate::compare::{compare_images, DiffResult, Region};
-use anyhow::Result;
+use crate::compare::{compare_images, DiffResult};
+use crate::error::{DiffError, Result};
+use crate::frames::compare_frames;
+use crate::options::CompareOptions;
+use crate::svg;
+use image::ImageReader;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use serde::Serialize;
 use walkdir::WalkDir;
 
 #[derive(Serialize)]
 #[serde(tag = "type", content = "data")]
 pub enum DirDiffStatus {
-    Match(DiffResult),
+    Match(Box<DiffResult>),
     MissingInB,
-    Error(String),
+    /// Only used for frames, when the second image has more frames than the first
+    MissingInA,
+    /// The file in the first directory is not an image that can be decoded
+    Skipped(String),
+    Error(DiffError),
+}
+
+/// Position and timing of a frame, for items of animated or multi-page images.
+#[derive(Serialize, Clone, Debug)]
+pub struct FrameInfo {
+    pub index: usize,
+    pub delay_a_ms: Option<u32>,
+    pub delay_b_ms: Option<u32>,
+}
+
+impl FrameInfo {
+    pub fn timing_mismatch(&self) -> bool {
+        self.delay_a_ms != self.delay_b_ms
+    }
 }
 
 #[derive(Serialize)]
 pub struct DirDiffItem {
     pub relative_path: PathBuf,
+    /// Set when `CompareOptions::frames` splits an animated or multi-page image into frames
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame: Option<FrameInfo>,
     pub status: DirDiffStatus,
 }
 
+impl DirDiffItem {
+    /// Whether the item differs in more pixels than `options` allow, or in frame timing or count.
+    pub fn fails(&self, options: &CompareOptions) -> bool {
+        let Some(frame) = &self.frame else {
+            return matches!(&self.status, DirDiffStatus::Match(res) if res.fails(options));
+        };
+        match &self.status {
+            DirDiffStatus::Match(res) => res.fails(options) || frame.timing_mismatch(),
+            DirDiffStatus::MissingInA | DirDiffStatus::MissingInB => true,
+            DirDiffStatus::Skipped(_) | DirDiffStatus::Error(_) => false,
+        }
+    }
+}
+
+/// Compares every file under `dir_a` with the file at the same relative path under `dir_b`.
+///
+/// Images requested through `options` (diff images, SSIM maps, canvases) are kept only for items
+/// that fail. Once `options.max_failures` files have failed or are missing in
+/// `dir_b`, the files not yet started are reported as skipped; comparisons already running finish.
 pub fn compare_directories(
     dir_a: &Path,
     dir_b: &Path,
-    threshold: f32,
-    ignore_regions: &[Region],
-    mask_path: Option<&Path>,
+    options: &CompareOptions,
 ) -> Result<Vec<DirDiffItem>> {
+    options.validate()?;
+    for dir in [dir_a, dir_b] {
+        if !dir.is_dir() {
+            return Err(DiffError::MissingFile(dir.to_path_buf()));
+        }
+    }
+
     let files_a: Vec<PathBuf> = WalkDir::new(dir_a)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| is_image(e.path()))
+        .filter(|e| e.file_type().is_file())
         .map(|e| e.path().to_path_buf())
         .collect();
 
@@ -39,27 +89,39 @@ pub fn compare_directories(
         .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
         .unwrap());
 
+    let failures = AtomicUsize::new(0);
+
     let results: Vec<DirDiffItem> = files_a
         .into_par_iter()
-        .map(|path_a| {
+        .flat_map_iter(|path_a| {
             let relative = path_a.strip_prefix(dir_a).unwrap();
             let path_b = dir_b.join(relative);
 
-            let status = if !path_b.exists() {
-                DirDiffStatus::MissingInB
-            } else {
-                match compare_images(&path_a, &path_b, threshold, false, ignore_regions, mask_path) {
-                    Ok(res) => DirDiffStatus::Match(res),
-                    Err(e) => DirDiffStatus::Error(e.to_string()),
-                }
+            let item = |status| DirDiffItem { relative_path: relative.to_path_buf(), frame: None, status };
+
+            if let Some(max) = options.max_failures.filter(|&max| failures.load(Ordering::Relaxed) >= max) {
+                pb.inc(1);
+                let reason = format!("not compared: limit of {} failing files reached", max);
+                return vec![item(DirDiffStatus::Skipped(reason))];
+            }
+
+            let items = match skip_reason(&path_a) {
+                Err(e) => vec![item(DirDiffStatus::Error(e))],
+                Ok(Some(reason)) => vec![item(DirDiffStatus::Skipped(reason))],
+                Ok(None) if !path_b.exists() => vec![item(DirDiffStatus::MissingInB)],
+                Ok(None) if options.frames => frame_items(relative, &path_a, &path_b, options),
+                Ok(None) => match compare_images(&path_a, &path_b, options) {
+                    Ok(res) => vec![item(matched(res, options))],
+                    Err(e) => vec![item(DirDiffStatus::Error(e))],
+                },
             };
 
+            if items.iter().any(|i| i.fails(options) || matches!(i.status, DirDiffStatus::MissingInB)) {
+                failures.fetch_add(1, Ordering::Relaxed);
+            }
             pb.inc(1);
 
-            DirDiffItem {
-                relative_path: relative.to_path_buf(),
-                status,
-            }
+            items
         })
         .collect();
 
@@ -67,7 +129,130 @@ pub fn compare_directories(
     Ok(results)
 }
 
-fn is_image(path: &Path) -> bool {
-    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
-    matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "webp" | "bmp")
+/// Wraps a result, dropping the images rendered for passing pairs so that only failing items
+/// keep theirs in memory.
+fn matched(mut res: DiffResult, options: &CompareOptions) -> DirDiffStatus {
+    if !res.fails(options) {
+        res.diff_image = None;
+        res.diff_image_16 = None;
+        res.ssim_map = None;
+        res.canvas = None;
+    }
+    DirDiffStatus::Match(Box::new(res))
+}
+
+/// Compares a file pair frame by frame, with one item per frame unless both are still images.
+fn frame_items(relative: &Path, path_a: &Path, path_b: &Path, options: &CompareOptions) -> Vec<DirDiffItem> {
+    let item = |frame, status| DirDiffItem { relative_path: relative.to_path_buf(), frame, status };
+
+    let res = match compare_frames(path_a, path_b, options) {
+        Ok(res) => res,
+        Err(e) => return vec![item(None, DirDiffStatus::Error(e))],
+    };
+
+    if res.frame_count_a == 1 && res.frame_count_b == 1 {
+        let result = res.frames.into_iter().next().map(|f| f.result).unwrap();
+        return vec![item(None, matched(result, options))];
+    }
+
+    let compared = res.frames.len();
+    let mut items: Vec<_> = res
+        .frames
+        .into_iter()
+        .map(|f| {
+            let info = FrameInfo { index: f.index, delay_a_ms: f.delay_a_ms, delay_b_ms: f.delay_b_ms };
+            item(Some(info), matched(f.result, options))
+        })
+        .collect();
+
+    let unmatched = |index| FrameInfo { index, delay_a_ms: None, delay_b_ms: None };
+    items.extend((compared..res.frame_count_a).map(|i| item(Some(unmatched(i)), DirDiffStatus::MissingInB)));
+    items.extend((compared..res.frame_count_b).map(|i| item(Some(unmatched(i)), DirDiffStatus::MissingInA)));
+    items
+}
+
+/// Why a file cannot be compared, or `None` if it is a decodable image.
+///
+/// Formats are detected by magic bytes, with the extension as a fallback for formats without a
+/// signature such as TGA. SVG documents are rasterized, so they are not skipped.
+fn skip_reason(path: &Path) -> Result<Option<String>> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| DiffError::Io { path: path.to_path_buf(), source: e })?;
+
+    Ok(match reader.format() {
+        Some(format) if format.reading_enabled() => None,
+        Some(format) => Some(format!("{:?} decoding is not enabled in this build", format)),
+        None if svg::is_svg(path) => None,
+        None => Some("not a recognised image format".to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    #[test]
+    fn test_sniffed_formats_and_skipped_files() -> anyhow::Result<()> {
+        let (dir_a, dir_b) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let img = RgbaImage::from_pixel(3, 3, Rgba([10, 20, 30, 255]));
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="3" height="3"/>"#;
+        for dir in [&dir_a, &dir_b] {
+            // No extension: detected from the PNG signature
+            img.save_with_format(dir.path().join("screenshot"), ImageFormat::Png)?;
+            img.save(dir.path().join("icon.qoi"))?;
+            img.save(dir.path().join("sprite.tga"))?;
+            std::fs::write(dir.path().join("logo.svg"), svg)?;
+            std::fs::write(dir.path().join("notes.txt"), "not an image")?;
+            // Mentions an SVG tag but is not an SVG document
+            std::fs::write(dir.path().join("notes.md"), format!("# Logo\n\n{}\n", svg))?;
+        }
+
+        let mut items = compare_directories(dir_a.path(), dir_b.path(), &CompareOptions::default())?;
+        items.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+        let statuses: Vec<_> = items
+            .iter()
+            .map(|item| {
+                let status = match &item.status {
+                    DirDiffStatus::Match(res) if res.diff_pixels == 0 => "match",
+                    DirDiffStatus::Skipped(_) => "skipped",
+                    _ => "other",
+                };
+                (item.relative_path.to_str().unwrap().to_string(), status)
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("icon.qoi", "match"),
+                ("logo.svg", "match"),
+                ("notes.md", "skipped"),
+                ("notes.txt", "skipped"),
+                ("screenshot", "match"),
+                ("sprite.tga", "match"),
+            ]
+            .map(|(p, s)| (p.to_string(), s))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_images_kept_only_for_differences() -> anyhow::Result<()> {
+        let (dir_a, dir_b) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let img = RgbaImage::from_pixel(3, 3, Rgba([10, 20, 30, 255]));
+        for dir in [&dir_a, &dir_b] {
+            img.save(dir.path().join("same.png"))?;
+        }
+        img.save(dir_a.path().join("changed.png"))?;
+        RgbaImage::from_pixel(3, 3, Rgba([200, 20, 30, 255])).save(dir_b.path().join("changed.png"))?;
+
+        let options = CompareOptions::builder().generate_diff(true).build();
+        for item in compare_directories(dir_a.path(), dir_b.path(), &options)? {
+            let DirDiffStatus::Match(res) = item.status else { panic!("unexpected status") };
+            assert_eq!(res.diff_image.is_some(), item.relative_path.ends_with("changed.png"));
+        }
+        Ok(())
+    }
 }
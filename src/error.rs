@@ -0,0 +1,153 @@
+//! Error type and process exit codes shared by the library and the CLI.
+
+use crate::svg::{self, SvgOptions};
+use image::ImageDecoder;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Exit code when every compared image is identical (or differences were not requested to fail).
+pub const EXIT_IDENTICAL: i32 = 0;
+/// Exit code when differences were found and `--fail-on-diff` is set.
+pub const EXIT_DIFFERENCES: i32 = 1;
+/// Exit code when an image could not be compared.
+pub const EXIT_ERROR: i32 = 2;
+/// Exit code when an input file is missing.
+pub const EXIT_MISSING: i32 = 3;
+
+pub type Result<T, E = DiffError> = std::result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum DiffError {
+    #[error("Failed to decode {path}: {source}")]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+
+    #[error("File not found: {0}")]
+    MissingFile(PathBuf),
+
+    #[error("Image dimensions differ: {}x{} vs {}x{}", .a.0, .a.1, .b.0, .b.1)]
+    DimensionMismatch { a: (u32, u32), b: (u32, u32) },
+
+    #[error("I/O error on {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid mask {path}: {reason}")]
+    InvalidMask { path: PathBuf, reason: String },
+
+    #[error("Cannot apply colour profile of {path}: {reason}")]
+    ColorProfile { path: PathBuf, reason: String },
+
+    #[error("Failed to encode {path}: {reason}")]
+    Encode { path: PathBuf, reason: String },
+
+    #[error("Invalid options: {0}")]
+    InvalidOptions(String),
+}
+
+impl DiffError {
+    /// Short machine-readable name used in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DiffError::Decode { .. } => "decode",
+            DiffError::MissingFile(_) => "missing_file",
+            DiffError::DimensionMismatch { .. } => "dimension_mismatch",
+            DiffError::Io { .. } => "io",
+            DiffError::InvalidMask { .. } => "invalid_mask",
+            DiffError::ColorProfile { .. } => "color_profile",
+            DiffError::Encode { .. } => "encode",
+            DiffError::InvalidOptions(_) => "invalid_options",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DiffError::MissingFile(_) => EXIT_MISSING,
+            _ => EXIT_ERROR,
+        }
+    }
+
+    /// Maps an `image::open` failure onto the matching variant.
+    pub(crate) fn from_image(path: &Path, err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                DiffError::MissingFile(path.to_path_buf())
+            }
+            image::ImageError::IoError(e) => DiffError::Io { path: path.to_path_buf(), source: e },
+            e => DiffError::Decode { path: path.to_path_buf(), source: e },
+        }
+    }
+}
+
+impl Serialize for DiffError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("DiffError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// Opens an image, mapping failures onto `DiffError`.
+///
+/// The format is detected from the file's magic bytes, falling back to its extension for formats
+/// without a signature, so misnamed and extensionless files decode too. Files in no raster format
+/// that are SVG documents are rasterized according to `svg`.
+pub(crate) fn open_image(path: &Path, svg: &SvgOptions) -> Result<image::DynamicImage> {
+    let reader = open_reader(path)?;
+    if reader.format().is_none() && svg::is_svg(path) {
+        return svg::rasterize(path, svg).map(image::DynamicImage::ImageRgba8);
+    }
+    reader.decode().map_err(|e| DiffError::from_image(path, e))
+}
+
+fn open_reader(path: &Path) -> Result<image::ImageReader<std::io::BufReader<std::fs::File>>> {
+    image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| DiffError::from_image(path, image::ImageError::IoError(e)))
+}
+
+/// Like [`open_image`], but also returns the embedded ICC profile, if any.
+pub(crate) fn open_image_with_profile(
+    path: &Path,
+    svg: &SvgOptions,
+) -> Result<(image::DynamicImage, Option<Vec<u8>>)> {
+    let reader = open_reader(path)?;
+    if reader.format().is_none() && svg::is_svg(path) {
+        return Ok((svg::rasterize(path, svg).map(image::DynamicImage::ImageRgba8)?, None));
+    }
+    let to_err = |e| DiffError::from_image(path, e);
+    let mut decoder = reader.into_decoder().map_err(to_err)?;
+    let icc = decoder.icc_profile().map_err(to_err)?;
+    let img = image::DynamicImage::from_decoder(decoder).map_err(to_err)?;
+    Ok((img, icc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_missing_and_undecodable() {
+        let err = open_image(Path::new("/definitely/not/here.png"), &SvgOptions::default()).unwrap_err();
+        assert!(matches!(err, DiffError::MissingFile(_)));
+        assert_eq!(err.exit_code(), EXIT_MISSING);
+
+        let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        std::fs::write(file.path(), b"not a png").unwrap();
+        let err = open_image(file.path(), &SvgOptions::default()).unwrap_err();
+        assert!(matches!(err, DiffError::Decode { .. }));
+        assert_eq!(err.exit_code(), EXIT_ERROR);
+
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "decode");
+    }
+}
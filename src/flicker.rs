@@ -0,0 +1,160 @@
+//! Animated blink comparisons that alternate between the two images.
+
+use crate::error::{DiffError, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, Frame, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Container format of a flicker animation.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlickerFormat {
+    #[default]
+    Gif,
+    Apng,
+}
+
+impl FlickerFormat {
+    /// Infers the format from a file extension (`.gif`, `.png` or `.apng`).
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        ext.parse().map_err(|e: anyhow::Error| DiffError::Encode { path: path.to_path_buf(), reason: e.to_string() })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FlickerFormat::Gif => "gif",
+            FlickerFormat::Apng => "png",
+        }
+    }
+}
+
+impl FromStr for FlickerFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gif" => Ok(FlickerFormat::Gif),
+            "apng" | "png" => Ok(FlickerFormat::Apng),
+            "webp" => Err(anyhow::anyhow!("Animated WebP encoding is not supported; use gif or apng")),
+            _ => Err(anyhow::anyhow!("Unknown flicker format '{}' (expected gif or apng)", s)),
+        }
+    }
+}
+
+impl fmt::Display for FlickerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlickerFormat::Gif => f.write_str("gif"),
+            FlickerFormat::Apng => f.write_str("apng"),
+        }
+    }
+}
+
+// Frames of different sizes are placed top-left on a canvas that fits them all
+fn uniform_frames(frames: &[&RgbaImage]) -> Vec<RgbaImage> {
+    let width = frames.iter().map(|f| f.width()).max().unwrap_or(0);
+    let height = frames.iter().map(|f| f.height()).max().unwrap_or(0);
+    frames
+        .iter()
+        .map(|f| {
+            if f.dimensions() == (width, height) {
+                (*f).clone()
+            } else {
+                let mut canvas = RgbaImage::new(width, height);
+                imageops::replace(&mut canvas, *f, 0, 0);
+                canvas
+            }
+        })
+        .collect()
+}
+
+/// APNG frame delay as a `u16` fraction of a second, coarsening the unit for delays over 65535 ms.
+fn apng_delay(delay_ms: u32) -> Option<(u16, u16)> {
+    [(1, 1000), (10, 100), (100, 10), (1000, 1)]
+        .into_iter()
+        .find_map(|(unit, den)| u16::try_from((delay_ms as u64 + unit / 2) / unit).ok().map(|num| (num, den)))
+}
+
+/// Writes a looping animation cycling through `frames`, showing each for `delay_ms`.
+pub fn write_flicker(path: &Path, format: FlickerFormat, frames: &[&RgbaImage], delay_ms: u32) -> Result<()> {
+    let frames = uniform_frames(frames);
+    let encode_err = |reason: String| DiffError::Encode { path: path.to_path_buf(), reason };
+
+    let file = File::create(path).map_err(|e| DiffError::Io { path: path.to_path_buf(), source: e })?;
+    let writer = BufWriter::new(file);
+
+    match format {
+        FlickerFormat::Gif => {
+            let mut encoder = GifEncoder::new(writer);
+            encoder.set_repeat(Repeat::Infinite).map_err(|e| encode_err(e.to_string()))?;
+            let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+            encoder
+                .encode_frames(frames.into_iter().map(|f| Frame::from_parts(f, 0, 0, delay)))
+                .map_err(|e| encode_err(e.to_string()))
+        }
+        FlickerFormat::Apng => {
+            let (width, height) = frames.first().map_or((0, 0), |f| f.dimensions());
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, 0).map_err(|e| encode_err(e.to_string()))?;
+            let (num, den) = apng_delay(delay_ms)
+                .ok_or_else(|| encode_err(format!("frame delay of {} ms is too long for APNG", delay_ms)))?;
+            encoder.set_frame_delay(num, den).map_err(|e| encode_err(e.to_string()))?;
+
+            let mut writer = encoder.write_header().map_err(|e| encode_err(e.to_string()))?;
+            for frame in &frames {
+                writer.write_image_data(frame.as_raw()).map_err(|e| encode_err(e.to_string()))?;
+            }
+            writer.finish().map_err(|e| encode_err(e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{AnimationDecoder, Rgba};
+
+    #[test]
+    fn test_gif_round_trip() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("flicker.gif");
+        let img_a = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let img_b = RgbaImage::from_pixel(4, 6, Rgba([0, 0, 255, 255]));
+
+        write_flicker(&path, FlickerFormat::from_path(&path)?, &[&img_a, &img_b], 300)?;
+
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::BufReader::new(File::open(&path)?))?;
+        let frames = decoder.into_frames().collect_frames()?;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].buffer().dimensions(), (4, 6));
+        assert_eq!(frames[0].delay().numer_denom_ms(), (300, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apng_and_webp() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("flicker.png");
+        let img = RgbaImage::from_pixel(3, 3, Rgba([0, 255, 0, 255]));
+        write_flicker(&path, FlickerFormat::Apng, &[&img, &img, &img], 500)?;
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path)?));
+        let reader = decoder.read_info()?;
+        assert_eq!(reader.info().animation_control().map(|a| a.num_frames), Some(3));
+
+        assert_eq!(apng_delay(500), Some((500, 1000)));
+        assert_eq!(apng_delay(90_000), Some((9000, 100)));
+        assert_eq!(apng_delay(u32::MAX), None);
+
+        let err = FlickerFormat::from_path(Path::new("out.webp")).unwrap_err();
+        assert!(matches!(err, DiffError::Encode { .. }));
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Frame-by-frame comparison of animated (GIF, APNG, WebP) and multi-page (TIFF) images.
+
+use crate::compare::{compare_buffers, compare_buffers_native, compare_images, DiffResult};
+use crate::error::{open_image, DiffError, Result};
+use crate::options::CompareOptions;
+use crate::precision::Precision;
+use crate::svg::SvgOptions;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::error::{DecodingError, ImageFormatHint};
+use image::{AnimationDecoder, DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageReader};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Seek};
+use std::path::Path;
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::{ColorType as TiffColor, TiffError, TiffFormatError, TiffUnsupportedError};
+
+/// One decoded frame or page.
+pub struct Frame {
+    pub image: DynamicImage,
+    /// Display time of the frame; `None` for pages of multi-page documents
+    pub delay_ms: Option<u32>,
+}
+
+/// Comparison of one pair of frames at the same index.
+#[derive(Serialize)]
+pub struct FrameResult {
+    pub index: usize,
+    pub delay_a_ms: Option<u32>,
+    pub delay_b_ms: Option<u32>,
+    pub result: DiffResult,
+}
+
+impl FrameResult {
+    pub fn timing_mismatch(&self) -> bool {
+        self.delay_a_ms != self.delay_b_ms
+    }
+
+    /// Whether the frames differ in more pixels than `options` allow, or in timing.
+    pub fn diverges(&self, options: &CompareOptions) -> bool {
+        self.result.fails(options) || self.timing_mismatch()
+    }
+}
+
+/// Result of comparing every frame of two images.
+#[derive(Serialize)]
+pub struct FramesResult {
+    pub frame_count_a: usize,
+    pub frame_count_b: usize,
+    /// Frames present in both images, in order
+    pub frames: Vec<FrameResult>,
+    /// Indices of compared frames whose delays differ
+    pub timing_mismatches: Vec<usize>,
+    /// First frame that fails in pixels or differs in timing, or the first unmatched frame when
+    /// the frame counts differ
+    pub first_divergent_frame: Option<usize>,
+}
+
+/// Decodes every frame of `path`; still images and SVG documents yield a single frame without a delay.
+pub fn load_frames(path: &Path, svg: &SvgOptions) -> Result<Vec<Frame>> {
+    match animated_frames(path)? {
+        Some(frames) => Ok(frames),
+        None => Ok(vec![Frame { image: open_image(path, svg)?, delay_ms: None }]),
+    }
+}
+
+/// Compares two images frame by frame.
+///
+/// Pairs of still images go through [`compare_images`], so colour management applies to them;
+/// frames of animations and multi-page documents are compared as decoded.
+pub fn compare_frames(path_a: &Path, path_b: &Path, options: &CompareOptions) -> Result<FramesResult> {
+    options.validate()?;
+    let (frames_a, frames_b) = match (animated_frames(path_a)?, animated_frames(path_b)?) {
+        (None, None) => {
+            let result = compare_images(path_a, path_b, options)?;
+            let frame = FrameResult { index: 0, delay_a_ms: None, delay_b_ms: None, result };
+            return Ok(summarize(1, 1, vec![frame], options));
+        }
+        (frames_a, frames_b) => (
+            frames_a.map_or_else(|| load_frames(path_a, &options.svg), Ok)?,
+            frames_b.map_or_else(|| load_frames(path_b, &options.svg), Ok)?,
+        ),
+    };
+
+    let frames = frames_a
+        .par_iter()
+        .zip(&frames_b)
+        .enumerate()
+        .map(|(index, (a, b))| {
+            let result = match options.precision {
+                Precision::EightBit => compare_buffers(&a.image.to_rgba8(), &b.image.to_rgba8(), options)?,
+                Precision::Native => compare_buffers_native(&a.image.to_rgba32f(), &b.image.to_rgba32f(), options)?,
+            };
+            Ok(FrameResult { index, delay_a_ms: a.delay_ms, delay_b_ms: b.delay_ms, result })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(summarize(frames_a.len(), frames_b.len(), frames, options))
+}
+
+fn summarize(
+    frame_count_a: usize,
+    frame_count_b: usize,
+    frames: Vec<FrameResult>,
+    options: &CompareOptions,
+) -> FramesResult {
+    let timing_mismatches = frames.iter().filter(|f| f.timing_mismatch()).map(|f| f.index).collect();
+    let first_divergent_frame = frames
+        .iter()
+        .find(|f| f.diverges(options))
+        .map(|f| f.index)
+        .or_else(|| (frame_count_a != frame_count_b).then(|| frame_count_a.min(frame_count_b)));
+
+    FramesResult { frame_count_a, frame_count_b, frames, timing_mismatches, first_divergent_frame }
+}
+
+/// Decodes the frames of animations and multi-page documents, or returns `None` for still images.
+fn animated_frames(path: &Path) -> Result<Option<Vec<Frame>>> {
+    let to_err = |e| DiffError::from_image(path, e);
+    let open = || {
+        let file = File::open(path).map_err(|e| to_err(ImageError::IoError(e)))?;
+        Ok::<_, DiffError>(BufReader::new(file))
+    };
+
+    let reader = ImageReader::new(open()?).with_guessed_format().map_err(|e| to_err(ImageError::IoError(e)))?;
+    let frames = match reader.format() {
+        Some(ImageFormat::Gif) => GifDecoder::new(open()?).map_err(to_err)?.into_frames(),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(open()?).map_err(to_err)?;
+            if !decoder.is_apng().map_err(to_err)? {
+                return Ok(None);
+            }
+            decoder.apng().map_err(to_err)?.into_frames()
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(open()?).map_err(to_err)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        Some(ImageFormat::Tiff) => return tiff_pages(open()?).map_err(to_err),
+        _ => return Ok(None),
+    };
+
+    let frames = frames.collect_frames().map_err(to_err)?;
+    Ok(Some(
+        frames
+            .into_iter()
+            .map(|f| {
+                let (numer, denom) = f.delay().numer_denom_ms();
+                Frame { delay_ms: Some(numer / denom.max(1)), image: DynamicImage::ImageRgba8(f.into_buffer()) }
+            })
+            .collect(),
+    ))
+}
+
+/// Decodes every page of a TIFF with more than one page.
+fn tiff_pages<R: std::io::Read + Seek>(reader: R) -> std::result::Result<Option<Vec<Frame>>, ImageError> {
+    let hint = || ImageFormatHint::Exact(ImageFormat::Tiff);
+    let tiff_err = |e: TiffError| ImageError::Decoding(DecodingError::new(hint(), e));
+
+    let mut decoder = TiffDecoder::new(reader).map_err(tiff_err)?;
+    if !decoder.more_images() {
+        return Ok(None);
+    }
+
+    let mut frames = Vec::new();
+    loop {
+        let (w, h) = decoder.dimensions().map_err(tiff_err)?;
+        let color = decoder.colortype().map_err(tiff_err)?;
+        let image = match (color, decoder.read_image().map_err(tiff_err)?) {
+            (TiffColor::Gray(8), DecodingResult::U8(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLuma8),
+            (TiffColor::GrayA(8), DecodingResult::U8(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLumaA8),
+            (TiffColor::RGB(8), DecodingResult::U8(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgb8),
+            (TiffColor::RGBA(8), DecodingResult::U8(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgba8),
+            (TiffColor::Gray(16), DecodingResult::U16(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLuma16),
+            (TiffColor::GrayA(16), DecodingResult::U16(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLumaA16),
+            (TiffColor::RGB(16), DecodingResult::U16(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgb16),
+            (TiffColor::RGBA(16), DecodingResult::U16(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgba16),
+            (TiffColor::RGB(32), DecodingResult::F32(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgb32F),
+            (TiffColor::RGBA(32), DecodingResult::F32(d)) => ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgba32F),
+            (color, _) => return Err(tiff_err(TiffUnsupportedError::UnsupportedColorType(color).into())),
+        };
+        let image = image.ok_or_else(|| tiff_err(TiffFormatError::InconsistentSizesEncountered.into()))?;
+        frames.push(Frame { image, delay_ms: None });
+
+        if !decoder.more_images() {
+            return Ok(Some(frames));
+        }
+        decoder.next_image().map_err(tiff_err)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flicker::{write_flicker, FlickerFormat};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_frame_count_and_divergence() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let red = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+
+        let path_a = dir.path().join("a.gif");
+        let path_b = dir.path().join("b.gif");
+        write_flicker(&path_a, FlickerFormat::Gif, &[&red, &red, &blue], 100)?;
+        write_flicker(&path_b, FlickerFormat::Gif, &[&red, &blue], 100)?;
+
+        let res = compare_frames(&path_a, &path_b, &CompareOptions::default())?;
+        assert_eq!((res.frame_count_a, res.frame_count_b), (3, 2));
+        assert_eq!(res.frames.len(), 2);
+        assert_eq!(res.frames[0].result.diff_pixels, 0);
+        assert_eq!(res.frames[1].result.diff_pixels, 16);
+        assert_eq!(res.first_divergent_frame, Some(1));
+        assert!(res.timing_mismatches.is_empty());
+
+        // Frames within the allowed diff pixels do not diverge; the extra frame still does
+        let options = CompareOptions::builder().max_diff_pixels(Some(16)).build();
+        let res = compare_frames(&path_a, &path_b, &options)?;
+        assert_eq!(res.first_divergent_frame, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_timing_mismatch_and_stills() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let img = RgbaImage::from_pixel(3, 3, Rgba([0, 255, 0, 255]));
+
+        let path_a = dir.path().join("a.png");
+        let path_b = dir.path().join("b.png");
+        write_flicker(&path_a, FlickerFormat::Apng, &[&img, &img], 100)?;
+        write_flicker(&path_b, FlickerFormat::Apng, &[&img, &img], 250)?;
+
+        let res = compare_frames(&path_a, &path_b, &CompareOptions::default())?;
+        assert_eq!(res.timing_mismatches, vec![0, 1]);
+        assert_eq!(res.first_divergent_frame, Some(0));
+        assert_eq!(res.frames[0].delay_b_ms, Some(250));
+
+        let still = dir.path().join("still.png");
+        img.save(&still)?;
+        let res = compare_frames(&still, &still, &CompareOptions::default())?;
+        assert_eq!((res.frame_count_a, res.first_divergent_frame), (1, None));
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+//! Visual difference detection for images.
+//!
+//! The library exposes the same comparison engine used by the `image-diff` CLI.
+//! Use [`compare::compare_images`] for files on disk, [`compare::compare_buffers`]
+//! for images that are already decoded, and [`dir::compare_directories`] to
+//! walk two directory trees.
+
+pub mod align;
+pub mod alpha;
+pub mod antialias;
+pub mod cluster;
+pub mod color;
+pub mod colormap;
+pub mod compare;
+pub mod dir;
+pub mod error;
+pub mod flicker;
+pub mod frames;
+pub mod metric;
+pub mod options;
+mod pixelmatch;
+pub mod precision;
+pub mod render;
+pub mod rows;
+pub mod size;
+pub mod ssim;
+pub mod svg;
+
+pub use alpha::AlphaMode;
+pub use antialias::AntiAliasing;
+pub use cluster::DiffRegion;
+pub use color::ColorManagement;
+pub use compare::{
+    compare_buffers, compare_buffers_native, compare_buffers_with_metric, compare_images, DiffResult, Region,
+};
+pub use dir::{compare_directories, DirDiffItem, DirDiffStatus, FrameInfo};
+pub use error::DiffError;
+pub use flicker::FlickerFormat;
+pub use frames::{compare_frames, FrameResult, FramesResult};
+pub use metric::{Metric, PixelMetric};
+pub use options::{Color, CompareMode, CompareOptions, CompareOptionsBuilder, PixelmatchOptions};
+pub use precision::{Precision, Tolerance};
+pub use render::DiffStyle;
+pub use rows::{RowBand, RowBandKind};
+pub use size::{Anchor, SizeMismatch};
+pub use ssim::{SsimAlgorithm, SsimColor, SsimMap, SsimMapStyle, SsimOptions, SsimRegion};
+pub use svg::{SvgOptions, SvgSize};
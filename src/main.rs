@@ -1,27 +1,19 @@
-mod compare;
-mod dir;
 mod terminal;
 
 use anyhow::Result;
 use clap::Parser;
 use colored::*;
-use std::path::PathBuf;
-
-use crate::compare::Region;
-use std::str::FromStr;
-
-impl FromStr for Region {
-    type Err = anyhow::Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<u32> = s.split(',')
-            .map(|p| p.parse::<u32>())
-            .collect::<std::result::Result<Vec<_>, _>>()?;
-        if parts.len() != 4 {
-            return Err(anyhow::anyhow!("Region must be in format x,y,width,height"));
-        }
-        Ok(Region { x: parts[0], y: parts[1], width: parts[2], height: parts[3] })
-    }
-}
+use image_diff::error::{EXIT_DIFFERENCES, EXIT_ERROR, EXIT_IDENTICAL, EXIT_MISSING};
+use image_diff::{
+    compare, dir, flicker, frames, render, ssim, AlphaMode, Anchor, AntiAliasing, Color, ColorManagement, CompareMode,
+    CompareOptions, DiffError, DiffResult, DiffStyle, DirDiffItem, FlickerFormat, FramesResult, Metric, PixelmatchOptions, Precision,
+    Region, SizeMismatch, SsimAlgorithm, SsimColor, SsimMapStyle, SsimOptions, SvgOptions, SvgSize, Tolerance,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+// Largest diff regions listed in the text summary
+const MAX_LISTED_REGIONS: usize = 5;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -36,10 +28,146 @@ struct Args {
     #[arg(short, long, default_value_t = 0.1)]
     threshold: f32,
 
+    /// Channel precision: 8bit (metric and threshold) or native (16-bit and float data with tolerances)
+    #[arg(long, default_value_t = Precision::default())]
+    precision: Precision,
+
+    /// Absolute per-channel tolerance at native precision (channels normalised to 0.0-1.0)
+    #[arg(long, default_value_t = 0.0)]
+    abs_tolerance: f32,
+
+    /// Relative per-channel tolerance at native precision, as a fraction of the larger value
+    #[arg(long, default_value_t = 0.0)]
+    rel_tolerance: f32,
+
+    /// Pixel difference metric: ciede2000, cie76, cie94, rgb, yiq or exact
+    #[arg(long, default_value_t = Metric::default())]
+    metric: Metric,
+
+    /// Comparison mode: standard or pixelmatch (pixelmatch-compatible counts and colours)
+    #[arg(long, default_value_t = CompareMode::default())]
+    mode: CompareMode,
+
+    /// Anti-aliasing detection: off, heuristic or full (standard mode)
+    #[arg(long, default_value_t = AntiAliasing::default())]
+    aa: AntiAliasing,
+
+    /// Count anti-aliased pixels as differences (pixelmatch mode)
+    #[arg(long)]
+    include_aa: bool,
+
+    /// Opacity of the original image in the diff output (pixelmatch mode)
+    #[arg(long, default_value_t = 0.1)]
+    diff_alpha: f32,
+
+    /// Colour of anti-aliased pixels as r,g,b or #rrggbb (pixelmatch mode)
+    #[arg(long, default_value = "255,255,0")]
+    aa_color: Color,
+
+    /// Colour of differing pixels as r,g,b or #rrggbb (pixelmatch mode)
+    #[arg(long, default_value = "255,0,0")]
+    diff_color: Color,
+
+    /// Colour of pixels that are darker in the second image (pixelmatch mode)
+    #[arg(long)]
+    diff_color_alt: Option<Color>,
+
+    /// Embedded ICC profiles: auto (convert to sRGB when possible), off, or force (fail if unusable)
+    #[arg(long, default_value_t = ColorManagement::default())]
+    color_manage: ColorManagement,
+
+    /// Raster size of SVG inputs in pixels, as W (keeping the aspect ratio) or WxH; overrides --svg-dpi
+    #[arg(long, value_name = "SIZE")]
+    svg_size: Option<SvgSize>,
+
+    /// Resolution at which SVG inputs are rasterized (96 renders one pixel per SVG unit)
+    #[arg(long, default_value_t = 96.0)]
+    svg_dpi: f32,
+
+    /// Opaque background painted under SVG inputs as r,g,b or #rrggbb (default: transparent)
+    #[arg(long)]
+    svg_background: Option<Color>,
+
+    /// Alpha handling: compare, ignore, premultiply or composite=<color> (e.g. composite=#ffffff)
+    #[arg(long, default_value_t = AlphaMode::default())]
+    alpha: AlphaMode,
+
+    /// Handling of images with different dimensions: fail, pad, crop or resize (default: pad; fail at native precision)
+    #[arg(long)]
+    size_mismatch: Option<SizeMismatch>,
+
+    /// Placement of the smaller image when padding or cropping (e.g. top-left, center, bottom-right)
+    #[arg(long, default_value_t = Anchor::default())]
+    anchor: Anchor,
+
+    /// Align the second image to the first by searching translations of up to this many pixels
+    #[arg(long, value_name = "MAX_OFFSET")]
+    align: Option<u32>,
+
+    /// Layout of the diff image: overlay, side-by-side, three-up, blend, onion or heatmap
+    #[arg(long, default_value_t = DiffStyle::default())]
+    diff_style: DiffStyle,
+
+    /// Append a colour scale below heatmap diff images
+    #[arg(long)]
+    legend: bool,
+
+    /// Outline each diff region, padded by this many pixels, in the diff image and preview
+    #[arg(long, value_name = "PADDING", num_args = 0..=1, default_missing_value = "4")]
+    boxes: Option<u32>,
+
+    /// Align rows like a text diff so inserted or removed content is reported as row bands
+    #[arg(long)]
+    align_rows: bool,
+
     /// Output path for diff overlay image (single file mode only)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Write an animation alternating A and B (.gif or .png for APNG); a directory in directory mode
+    #[arg(long, value_name = "PATH")]
+    flicker: Option<PathBuf>,
+
+    /// Time each flicker frame is shown, in milliseconds
+    #[arg(long, default_value_t = 500)]
+    flicker_delay: u32,
+
+    /// Add the diff image as a third flicker frame
+    #[arg(long)]
+    flicker_diff: bool,
+
+    /// Flicker format in directory mode: gif or apng
+    #[arg(long, default_value_t = FlickerFormat::default())]
+    flicker_format: FlickerFormat,
+
+    /// Output path for the per-pixel SSIM map image (single file mode only)
+    #[arg(long)]
+    ssim_map: Option<PathBuf>,
+
+    /// Rendering of the SSIM map: gray or heatmap
+    #[arg(long, default_value_t = SsimMapStyle::default())]
+    ssim_map_style: SsimMapStyle,
+
+    /// Tile size for per-region minimum SSIM reporting
+    #[arg(long, default_value_t = 64)]
+    ssim_tile_size: u32,
+
+    /// Skip the SSIM computation
+    #[arg(long)]
+    no_ssim: bool,
+
+    /// Channels used for SSIM: gray or rgb
+    #[arg(long, default_value_t = SsimColor::default())]
+    ssim_color: SsimColor,
+
+    /// Structural comparison algorithm: mssim or rms
+    #[arg(long, default_value_t = SsimAlgorithm::default())]
+    ssim_algorithm: SsimAlgorithm,
+
+    /// Window size in pixels for MSSIM
+    #[arg(long, default_value_t = 8)]
+    ssim_window: u32,
+
     /// Print preview in terminal
     #[arg(short, long)]
     preview: bool,
@@ -48,6 +176,28 @@ struct Args {
     #[arg(long)]
     fail_on_diff: bool,
 
+    /// Number of differing pixels allowed per image; once exceeded, scanning stops after rows in progress
+    /// and SSIM is skipped
+    #[arg(long, value_name = "N")]
+    max_diff_pixels: Option<u64>,
+
+    /// Fail on the first differing pixel and stop scanning once rows in progress finish; counts are then a
+    /// lower bound (same as --max-diff-pixels 0)
+    #[arg(long, conflicts_with = "max_diff_pixels")]
+    fail_fast: bool,
+
+    /// Stop a directory comparison after this many failing or missing files
+    #[arg(long, value_name = "N")]
+    max_failures: Option<usize>,
+
+    /// Worker threads shared by directory walking and per-image comparison (default: one per CPU)
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Compare every frame of animated (GIF, APNG, WebP) and multi-page (TIFF) images
+    #[arg(long)]
+    frames: bool,
+
     /// Output results in JSON format
     #[arg(long)]
     json: bool,
@@ -65,77 +215,448 @@ struct Args {
     mask: Option<PathBuf>,
 }
 
-fn main() -> Result<()> {
+/// JSON report for a single file comparison, echoing the effective options.
+#[derive(Serialize)]
+struct FileReport<'a> {
+    options: &'a CompareOptions,
+    #[serde(flatten)]
+    result: &'a DiffResult,
+}
+
+/// JSON report for a frame-by-frame comparison, echoing the effective options.
+#[derive(Serialize)]
+struct FramesReport<'a> {
+    options: &'a CompareOptions,
+    #[serde(flatten)]
+    result: &'a FramesResult,
+}
+
+/// JSON report for a directory comparison, echoing the effective options.
+#[derive(Serialize)]
+struct DirReport<'a> {
+    options: &'a CompareOptions,
+    items: &'a [DirDiffItem],
+}
+
+impl Args {
+    fn compare_options(&self, generate_diff: bool) -> Result<CompareOptions> {
+        // Native comparison needs same-sized images, so it defaults to failing on a size mismatch
+        let size_mismatch = match self.precision {
+            Precision::EightBit => self.size_mismatch.unwrap_or_default(),
+            Precision::Native => self.size_mismatch.unwrap_or(SizeMismatch::Fail),
+        };
+
+        let options = CompareOptions::builder()
+            .threshold(self.threshold)
+            .precision(self.precision)
+            .tolerance(Tolerance { absolute: self.abs_tolerance, relative: self.rel_tolerance })
+            .generate_diff(generate_diff)
+            .ignore_regions(self.ignore.iter().cloned())
+            .mask(self.mask.clone())
+            .metric(self.metric)
+            .mode(self.mode)
+            .anti_aliasing(self.aa)
+            .pixelmatch(PixelmatchOptions {
+                include_aa: self.include_aa,
+                alpha: self.diff_alpha,
+                aa_color: self.aa_color,
+                diff_color: self.diff_color,
+                diff_color_alt: self.diff_color_alt,
+            })
+            .color_management(self.color_manage)
+            .svg(SvgOptions { size: self.svg_size, dpi: self.svg_dpi, background: self.svg_background })
+            .alpha(self.alpha)
+            .size_mismatch(size_mismatch)
+            .anchor(self.anchor)
+            .align(self.align)
+            .align_rows(self.align_rows)
+            .boxes(self.boxes)
+            .diff_style(self.diff_style)
+            .legend(self.legend)
+            .frames(self.frames)
+            .max_diff_pixels(if self.fail_fast { Some(0) } else { self.max_diff_pixels })
+            .max_failures(self.max_failures)
+            .ssim(SsimOptions {
+                enabled: !self.no_ssim,
+                color: self.ssim_color,
+                algorithm: self.ssim_algorithm,
+                window: self.ssim_window,
+                generate_map: self.ssim_map.is_some(),
+                tile_size: self.ssim_tile_size,
+            })
+            .build();
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+fn main() {
     let args = Args::parse();
 
-    if args.path_a.is_dir() && args.path_b.is_dir() {
+    if let Some(jobs) = args.jobs {
+        // Directory and per-image parallelism both run on the global pool, so this bounds the total
+        if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global() {
+            eprintln!("{} {}", "Error:".red().bold(), e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+
+    let result = if args.path_a.is_dir() && args.path_b.is_dir() {
         run_dir_diff(&args)
     } else {
         run_file_diff(&args)
+    };
+
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("{} {:#}", "Error:".red().bold(), e);
+            let code = e.downcast_ref::<DiffError>().map_or(EXIT_ERROR, DiffError::exit_code);
+            std::process::exit(code);
+        }
     }
 }
 
-fn run_file_diff(args: &Args) -> Result<()> {
-    let res = compare::compare_images(
-        &args.path_a,
-        &args.path_b,
-        args.threshold,
-        args.output.is_some() || args.preview,
-        &args.ignore,
-        args.mask.as_deref(),
-    )?;
+/// Writes the flicker animation for a result compared with `generate_canvas` set.
+fn save_flicker(res: &DiffResult, path: &Path, format: FlickerFormat, args: &Args) -> Result<()> {
+    let Some((canvas_a, canvas_b)) = &res.canvas else {
+        return Ok(());
+    };
+    let mut frames = vec![canvas_a, canvas_b];
+    if args.flicker_diff {
+        frames.extend(&res.diff_image);
+    }
+    flicker::write_flicker(path, format, &frames, args.flicker_delay)?;
+    Ok(())
+}
+
+fn run_file_diff(args: &Args) -> Result<i32> {
+    if args.frames {
+        return run_frames_diff(args);
+    }
+
+    let generate_diff = args.output.is_some() || args.preview || (args.flicker.is_some() && args.flicker_diff);
+    let mut options = args.compare_options(generate_diff)?;
+    options.generate_canvas = args.flicker.is_some();
+    let res = compare::compare_images(&args.path_a, &args.path_b, &options)?;
+
+    if let Some(flicker_path) = &args.flicker {
+        save_flicker(&res, flicker_path, FlickerFormat::from_path(flicker_path)?, args)?;
+    }
+
+    if let (Some(ssim_map), Some(ssim_map_path)) = (&res.ssim_map, &args.ssim_map) {
+        ssim::render_ssim_map(ssim_map, args.ssim_map_style).save(ssim_map_path)?;
+    }
 
     if args.json {
-        println!("{}", serde_json::to_string_pretty(&res)?);
+        let report = FileReport { options: &options, result: &res };
+        println!("{}", serde_json::to_string_pretty(&report)?);
     } else {
         println!("{}", "Comparison Result:".bold());
         println!("  Pixel Similarity: {:.2}%", res.score * 100.0);
-        println!("  SSIM Score:       {:.4}", res.ssim_score);
+        println!("  SSIM Score:       {}", format_ssim(&res));
         println!("  Diff Pixels:      {}", res.diff_pixels);
+        println!("  AA Pixels:        {}", res.aa_pixels);
         println!("  Total Pixels:     {}", res.total_pixels);
 
+        if res.size_mismatch {
+            println!(
+                "  Size Mismatch:    {}x{} vs {}x{} ({})",
+                res.dimensions_a.0,
+                res.dimensions_a.1,
+                res.dimensions_b.0,
+                res.dimensions_b.1,
+                options.size_mismatch
+            );
+        }
+
+        if res.color_converted {
+            println!("  Colour Profile:   converted to sRGB");
+        }
+
+        if res.truncated {
+            let allowed = options.max_diff_pixels.unwrap_or(0);
+            println!(
+                "  Truncated:        stopped after more than {} diff pixels; counts are a lower bound",
+                allowed
+            );
+        }
+
+        if let Some((dx, dy)) = res.offset {
+            println!("  Offset:           dx={}, dy={}", dx, dy);
+        }
+
+        if !res.regions.is_empty() {
+            println!("  Diff Regions:     {}", res.regions.len());
+            let mut largest: Vec<_> = res.regions.iter().collect();
+            largest.sort_by_key(|r| std::cmp::Reverse(r.pixels));
+            for r in largest.iter().take(MAX_LISTED_REGIONS) {
+                println!(
+                    "    {},{} ({}x{}): {} px, mean {:.3}, max {:.3}",
+                    r.region.x, r.region.y, r.region.width, r.region.height, r.pixels, r.mean_distance, r.max_distance
+                );
+            }
+            if largest.len() > MAX_LISTED_REGIONS {
+                println!("    ... and {} more", largest.len() - MAX_LISTED_REGIONS);
+            }
+        }
+
+        for band in &res.row_bands {
+            println!(
+                "  Rows {:<9}    A {}..{}, B {}..{}",
+                format!("{}:", band.kind),
+                band.a_start,
+                band.a_start + band.a_rows,
+                band.b_start,
+                band.b_start + band.b_rows
+            );
+        }
+
+        if let Some(worst) = res.ssim_regions.iter().min_by(|a, b| a.min_ssim.total_cmp(&b.min_ssim)) {
+            println!(
+                "  Lowest SSIM Tile: {:.4} at {},{} ({}x{}), {} tiles below 1.0",
+                worst.min_ssim,
+                worst.region.x,
+                worst.region.y,
+                worst.region.width,
+                worst.region.height,
+                res.ssim_regions.len()
+            );
+        }
+
+        if let (Some(_), Some(ssim_map_path)) = (&res.ssim_map, &args.ssim_map) {
+            println!("  SSIM map saved to: {}", ssim_map_path.display().to_string().cyan());
+        }
+
+        if let Some(flicker_path) = &args.flicker {
+            println!("  Flicker saved to: {}", flicker_path.display().to_string().cyan());
+        }
+
         if let Some(diff_img) = &res.diff_image {
             if let Some(output_path) = &args.output {
-                diff_img.save(output_path)?;
+                save_diff_image(&res, output_path)?;
                 println!("  Diff image saved to: {}", output_path.display().to_string().cyan());
+                if args.diff_style == DiffStyle::Heatmap {
+                    println!(
+                        "  Heatmap scale:    blue (0.0) to red (1.0) by square root of distance, threshold {} at {:.0}%",
+                        args.threshold,
+                        render::heat_position(args.threshold) * 100.0
+                    );
+                }
             }
 
             if args.preview {
                 println!("\n{}", "Terminal Preview:".bold());
                 let dynamic_img = image::DynamicImage::ImageRgba8(diff_img.clone());
-                terminal::print_preview(&dynamic_img);
+                terminal::print_preview(&dynamic_img, &preview_boxes(&res, args));
+            }
+        }
+    }
+
+    if args.fail_on_diff && res.fails(&options) {
+        return Ok(EXIT_DIFFERENCES);
+    }
+
+    Ok(EXIT_IDENTICAL)
+}
+
+fn run_frames_diff(args: &Args) -> Result<i32> {
+    let generate_diff = args.output.is_some() || args.preview || (args.flicker.is_some() && args.flicker_diff);
+    let mut options = args.compare_options(generate_diff)?;
+    options.generate_canvas = args.flicker.is_some();
+    let res = frames::compare_frames(&args.path_a, &args.path_b, &options)?;
+
+    // Outputs are written per failing frame, named like `diff.frame3.png`
+    let failing: Vec<_> = res.frames.iter().filter(|f| f.result.fails(&options)).collect();
+    for frame in &failing {
+        if let Some(output_path) = &args.output {
+            save_diff_image(&frame.result, &frame_path(output_path, frame.index))?;
+        }
+        if let Some(flicker_path) = &args.flicker {
+            let format = FlickerFormat::from_path(flicker_path)?;
+            save_flicker(&frame.result, &frame_path(flicker_path, frame.index), format, args)?;
+        }
+        if let (Some(ssim_map), Some(ssim_map_path)) = (&frame.result.ssim_map, &args.ssim_map) {
+            ssim::render_ssim_map(ssim_map, args.ssim_map_style).save(frame_path(ssim_map_path, frame.index))?;
+        }
+    }
+
+    if args.json {
+        let report = FramesReport { options: &options, result: &res };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", "Frame Comparison:".bold());
+        println!("  Frames:           {} vs {}", res.frame_count_a, res.frame_count_b);
+        println!("  Timing Mismatches: {}", res.timing_mismatches.len());
+        match res.first_divergent_frame {
+            Some(index) => println!("  First Divergence: frame {}", index),
+            None => println!("  First Divergence: {}", "none".green()),
+        }
+
+        println!("\n{:<8} {:<10} {:<10} {:<14} {:<10}", "Frame", "Pixel", "SSIM", "Delay (ms)", "Status");
+        println!("{}", "-".repeat(60));
+        for frame in &res.frames {
+            let status = if frame.result.fails(&options) {
+                "DIFF".red()
+            } else if frame.timing_mismatch() {
+                "TIMING".yellow()
+            } else {
+                "OK".green()
+            };
+            println!(
+                "{:<8} {:<10.2}% {:<10} {:<14} {:<10}",
+                frame.index,
+                frame.result.score * 100.0,
+                frame.result.ssim_score.map_or("-".to_string(), |s| format!("{:.4}", s)),
+                format_delays(frame.delay_a_ms, frame.delay_b_ms),
+                status
+            );
+        }
+
+        let (a, b) = (res.frame_count_a, res.frame_count_b);
+        if a != b {
+            let (side, range) = if a > b { ("first", b..a) } else { ("second", a..b) };
+            println!("Frames {}-{} exist only in the {} image", range.start, range.end - 1, side);
+        }
+
+        for frame in &failing {
+            let saved = |label: &str, path: &Path| {
+                let path = frame_path(path, frame.index);
+                println!("{} saved to: {}", label, path.display().to_string().cyan());
+            };
+            if let (Some(_), Some(output_path)) = (&frame.result.diff_image, &args.output) {
+                saved("Diff image", output_path);
+            }
+            if let Some(flicker_path) = &args.flicker {
+                saved("Flicker", flicker_path);
+            }
+            if let (Some(_), Some(ssim_map_path)) = (&frame.result.ssim_map, &args.ssim_map) {
+                saved("SSIM map", ssim_map_path);
+            }
+
+            if args.preview {
+                if let Some(diff_img) = &frame.result.diff_image {
+                    println!("\n{}", format!("Terminal Preview (frame {}):", frame.index).bold());
+                    let dynamic_img = image::DynamicImage::ImageRgba8(diff_img.clone());
+                    terminal::print_preview(&dynamic_img, &preview_boxes(&frame.result, args));
+                }
             }
         }
     }
 
-    if args.fail_on_diff && res.diff_pixels > 0 {
-        std::process::exit(1);
+    if args.fail_on_diff && res.first_divergent_frame.is_some() {
+        return Ok(EXIT_DIFFERENCES);
     }
 
+    Ok(EXIT_IDENTICAL)
+}
+
+/// Saves the diff image, at 16 bits per channel when it was rendered at native precision.
+fn save_diff_image(res: &DiffResult, path: &Path) -> Result<()> {
+    match (&res.diff_image_16, &res.diff_image) {
+        (Some(diff_img_16), _) => diff_img_16.save(path)?,
+        (None, Some(diff_img)) => diff_img.save(path)?,
+        (None, None) => {}
+    }
     Ok(())
 }
 
-fn run_dir_diff(args: &Args) -> Result<()> {
-    let items = dir::compare_directories(
-        &args.path_a, 
-        &args.path_b, 
-        args.threshold, 
-        &args.ignore,
-        args.mask.as_deref()
-    )?;
+/// Output path for frame `index` of `path`, e.g. `diff.png` becomes `diff.frame3.png`.
+fn frame_path(path: &Path, index: usize) -> PathBuf {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+    path.with_extension(format!("frame{}.{}", index, ext))
+}
+
+fn format_delays(a: Option<u32>, b: Option<u32>) -> String {
+    match (a, b) {
+        (None, None) => "-".to_string(),
+        _ => format!(
+            "{}/{}",
+            a.map_or("-".to_string(), |d| d.to_string()),
+            b.map_or("-".to_string(), |d| d.to_string())
+        ),
+    }
+}
+
+/// Label of a directory item, with the frame index for items of animated images.
+fn item_label(item: &DirDiffItem) -> String {
+    match &item.frame {
+        Some(frame) => format!("{} [frame {}]", item.relative_path.display(), frame.index),
+        None => item.relative_path.display().to_string(),
+    }
+}
+
+/// Padded diff region outlines to draw on the terminal preview, if `--boxes` is set.
+fn preview_boxes(res: &DiffResult, args: &Args) -> Vec<Region> {
+    let (Some(padding), Some(diff_img)) = (args.boxes, &res.diff_image) else {
+        return Vec::new();
+    };
+    let panels = args.diff_style.panels();
+    let width = diff_img.width() / panels;
+    (0..panels)
+        .flat_map(|panel| {
+            res.regions.iter().map(move |r| {
+                let mut b = render::padded_box(&r.region, padding, width, diff_img.height());
+                b.x += panel * width;
+                b
+            })
+        })
+        .collect()
+}
+
+fn format_ssim(res: &DiffResult) -> String {
+    match (res.ssim_score, &res.ssim_error) {
+        (Some(score), _) => format!("{:.4}", score),
+        (None, Some(e)) => format!("{} ({})", "unavailable".yellow(), e),
+        (None, None) => "skipped".dimmed().to_string(),
+    }
+}
+
+fn run_dir_diff(args: &Args) -> Result<i32> {
+    // Render everything review and flicker output need in the first pass instead of comparing twice.
+    // Review only runs in text mode, so JSON runs skip its diff images
+    let review = args.review && !args.json;
+    let mut options = args.compare_options(review || (args.flicker.is_some() && args.flicker_diff))?;
+    options.generate_canvas = args.flicker.is_some();
+    let items = dir::compare_directories(&args.path_a, &args.path_b, &options)?;
 
     let mut diff_count = 0;
+    let mut missing_count = 0;
+    let mut error_count = 0;
+    let mut skipped_count = 0;
+
+    for item in &items {
+        match item.status {
+            _ if item.fails(&options) => diff_count += 1,
+            dir::DirDiffStatus::MissingInB => missing_count += 1,
+            dir::DirDiffStatus::Skipped(_) => skipped_count += 1,
+            dir::DirDiffStatus::Error(_) => error_count += 1,
+            _ => {}
+        }
+    }
 
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&items)?);
-        // Calculate diff_count for exit code even in JSON mode
+    if let Some(flicker_dir) = &args.flicker {
         for item in &items {
-            match item.status {
-                dir::DirDiffStatus::Match(ref res) if res.diff_pixels > 0 => diff_count += 1,
-                dir::DirDiffStatus::MissingInB => diff_count += 1,
-                _ => {}
+            let dir::DirDiffStatus::Match(res) = &item.status else {
+                continue;
+            };
+            if !res.fails(&options) {
+                continue;
+            }
+            let mut path = flicker_dir.join(&item.relative_path).with_extension(args.flicker_format.extension());
+            if let Some(frame) = &item.frame {
+                path = frame_path(&path, frame.index);
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
             }
+            save_flicker(res, &path, args.flicker_format, args)?;
         }
+    }
+
+    if args.json {
+        let report = DirReport { options: &options, items: &items };
+        println!("{}", serde_json::to_string_pretty(&report)?);
     } else {
         println!("\n{:<40} {:<10} {:<10} {:<10}", "File", "Pixel", "SSIM", "Status");
         println!("{}", "-".repeat(75));
@@ -143,66 +664,82 @@ fn run_dir_diff(args: &Args) -> Result<()> {
         for item in &items {
             match item.status {
                 dir::DirDiffStatus::Match(ref res) => {
-                    let status = if res.diff_pixels > 0 {
-                        diff_count += 1;
+                    let status = if res.fails(&options) {
                         "DIFF".red()
+                    } else if item.fails(&options) {
+                        "TIMING".yellow()
                     } else {
                         "OK".green()
                     };
-                    println!("{:<40} {:<10.2}% {:<10.4} {:<10}", 
-                        item.relative_path.display().to_string(),
+                    println!("{:<40} {:<10.2}% {:<10} {:<10}", 
+                        item_label(item),
                         res.score * 100.0,
-                        res.ssim_score,
+                        res.ssim_score.map_or("-".to_string(), |s| format!("{:.4}", s)),
                         status
                     );
                 }
                 dir::DirDiffStatus::MissingInB => {
-                    diff_count += 1;
                     println!("{:<40} {:<10} {:<10}", 
-                        item.relative_path.display().to_string(),
+                        item_label(item),
                         "-".dimmed(),
                         "MISSING".yellow()
                     );
                 }
+                dir::DirDiffStatus::MissingInA => {
+                    println!("{:<40} {:<10} {:<10}",
+                        item_label(item),
+                        "-".dimmed(),
+                        "ADDED".yellow()
+                    );
+                }
+                dir::DirDiffStatus::Skipped(ref reason) => {
+                    println!("{:<40} {:<10} {:<10}",
+                        item_label(item),
+                        "SKIPPED".dimmed(),
+                        reason.dimmed()
+                    );
+                }
                 dir::DirDiffStatus::Error(ref e) => {
                     println!("{:<40} {:<10} {:<10}", 
-                        item.relative_path.display().to_string(),
+                        item_label(item),
                         "ERROR".red(),
-                        e.yellow()
+                        e.to_string().yellow()
                     );
                 }
             }
         }
 
-        println!("\nSummary: {} files compared, {} differences found.", 
-            items.len(), 
-            diff_count
+        println!("\nSummary: {} files compared, {} differences found, {} missing, {} errors, {} skipped.", 
+            items.len() - skipped_count, 
+            diff_count,
+            missing_count,
+            error_count,
+            skipped_count
         );
 
+        if let Some(flicker_dir) = &args.flicker {
+            if diff_count > 0 {
+                println!("Flicker animations saved to: {}", flicker_dir.display().to_string().cyan());
+            }
+        }
+
         if args.review && diff_count > 0 {
             use dialoguer::Select;
             println!("\n{}", "Entering Review Mode...".bold().yellow());
             
             for item in items {
                 if let dir::DirDiffStatus::Match(res) = &item.status {
-                    if res.diff_pixels > 0 {
+                    if res.fails(&options) {
                         println!("\n{}", "-".repeat(40));
-                        println!("Reviewing: {}", item.relative_path.display().to_string().bold().cyan());
+                        println!("Reviewing: {}", item_label(&item).bold().cyan());
                         println!("Pixel Similarity: {:.2}%", res.score * 100.0);
-                        
-                        // Regenerate diff for preview
-                        let full_res = compare::compare_images(
-                            &args.path_a.join(&item.relative_path),
-                            &args.path_b.join(&item.relative_path),
-                            args.threshold,
-                            true,
-                            &args.ignore,
-                            args.mask.as_deref(),
-                        )?;
-
-                        if let Some(diff_img) = full_res.diff_image {
+
+                        if let Some(diff_img) = &res.diff_image {
                             println!("{}", "Terminal Preview (Heatmap):".dimmed());
-                            terminal::print_preview(&image::DynamicImage::ImageRgba8(diff_img));
+                            terminal::print_preview(
+                                &image::DynamicImage::ImageRgba8(diff_img.clone()),
+                                &preview_boxes(res, args),
+                            );
                         }
 
                         let selections = &["Keep Original", "Accept New (Overwrite Original)", "Skip"];
@@ -224,9 +761,13 @@ fn run_dir_diff(args: &Args) -> Result<()> {
         }
     }
 
-    if args.fail_on_diff && diff_count > 0 {
-        std::process::exit(1);
+    if missing_count > 0 {
+        Ok(EXIT_MISSING)
+    } else if error_count > 0 {
+        Ok(EXIT_ERROR)
+    } else if args.fail_on_diff && diff_count > 0 {
+        Ok(EXIT_DIFFERENCES)
+    } else {
+        Ok(EXIT_IDENTICAL)
     }
-
-    Ok(())
 }
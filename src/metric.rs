@@ -0,0 +1,298 @@
+use image::Rgba;
+use lab::Lab;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fmt;
+use std::str::FromStr;
+
+/// Distance between two RGBA pixels.
+///
+/// Implementations return a value normalized so that black vs. white is roughly 1.0,
+/// which keeps the `--threshold` scale comparable across metrics.
+pub trait PixelMetric: Send + Sync {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64;
+}
+
+/// CIEDE2000 perceptual colour difference plus a scaled alpha term.
+pub struct Ciede2000;
+
+/// CIE76: plain Euclidean distance in Lab space.
+pub struct Cie76;
+
+/// CIE94 with the graphic arts weighting constants.
+pub struct Cie94;
+
+/// Euclidean distance over the raw RGBA channels.
+pub struct RgbEuclidean;
+
+/// Luma-weighted YIQ distance as used by pixelmatch, with alpha blended over white.
+pub struct Yiq;
+
+/// 0.0 when both pixels are byte-identical, 1.0 otherwise.
+pub struct Exact;
+
+// Scale alpha difference (0-255 -> 0-100 to match Lab scale roughly)
+fn alpha_diff(p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+    (p1[3] as f64 - p2[3] as f64).abs() / 2.55
+}
+
+// Screenshots use few distinct colours, so a small per-thread cache of sRGB -> Lab conversions
+// hits for almost every pixel, including neighbours revisited by anti-aliasing detection.
+const LAB_CACHE_SIZE: usize = 4096;
+// Packed RGB keys only use 24 bits, so this never matches a real colour
+const EMPTY_SLOT: u32 = u32::MAX;
+
+thread_local! {
+    static LAB_CACHE: Box<[Cell<(u32, Lab)>]> =
+        (0..LAB_CACHE_SIZE).map(|_| Cell::new((EMPTY_SLOT, Lab::default()))).collect();
+}
+
+/// Converts a pixel's sRGB colour to Lab, memoized in a direct-mapped per-thread cache.
+fn to_lab(p: &Rgba<u8>) -> Lab {
+    let key = u32::from_le_bytes([p[0], p[1], p[2], 0]);
+    // Fibonacci hashing: the top 12 bits of the product index the table
+    let slot = (key.wrapping_mul(0x9E37_79B9) >> 20) as usize;
+    LAB_CACHE.with(|cache| {
+        let (cached_key, lab) = cache[slot].get();
+        if cached_key == key {
+            return lab;
+        }
+        let lab = Lab::from_rgb(&[p[0], p[1], p[2]]);
+        cache[slot].set((key, lab));
+        lab
+    })
+}
+
+// The Lab metrics return early for identical pixels, skipping conversion and the formula.
+// They are not vectorized: conversions almost always hit the cache, and what remains is the
+// DE2000 formula, whose branches and atan2/sin/cos/exp calls have no SIMD form on stable Rust.
+impl PixelMetric for Ciede2000 {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        if p1 == p2 {
+            return 0.0;
+        }
+        // CIEDE2000 > 2.3 is usually "just noticeable difference" (JND).
+        // Dividing by 100.0 maps black vs white to roughly 1.0 for our threshold logic.
+        let color_diff = delta_e::DE2000::new(to_lab(p1), to_lab(p2)) as f64;
+        (color_diff + alpha_diff(p1, p2)) / 100.0
+    }
+}
+
+impl PixelMetric for Cie76 {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        if p1 == p2 {
+            return 0.0;
+        }
+        let color_diff = (to_lab(p1).squared_distance(&to_lab(p2)) as f64).sqrt();
+        (color_diff + alpha_diff(p1, p2)) / 100.0
+    }
+}
+
+impl PixelMetric for Cie94 {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        const K1: f64 = 0.045;
+        const K2: f64 = 0.015;
+
+        if p1 == p2 {
+            return 0.0;
+        }
+
+        let (lab1, lab2) = (to_lab(p1), to_lab(p2));
+        let delta_l = (lab1.l - lab2.l) as f64;
+        let c1 = ((lab1.a * lab1.a + lab1.b * lab1.b) as f64).sqrt();
+        let c2 = ((lab2.a * lab2.a + lab2.b * lab2.b) as f64).sqrt();
+        let delta_c = c1 - c2;
+        let delta_a = (lab1.a - lab2.a) as f64;
+        let delta_b = (lab1.b - lab2.b) as f64;
+        // Rounding can push the squared hue difference slightly negative
+        let delta_h_sq = (delta_a * delta_a + delta_b * delta_b - delta_c * delta_c).max(0.0);
+
+        let s_c = 1.0 + K1 * c1;
+        let s_h = 1.0 + K2 * c1;
+        let color_diff = (delta_l * delta_l + (delta_c / s_c).powi(2) + delta_h_sq / (s_h * s_h)).sqrt();
+
+        (color_diff + alpha_diff(p1, p2)) / 100.0
+    }
+}
+
+impl PixelMetric for RgbEuclidean {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        let sum: f64 = (0..4)
+            .map(|i| {
+                let d = p1[i] as f64 - p2[i] as f64;
+                d * d
+            })
+            .sum();
+        // Black vs white differs by 255 on three channels
+        sum.sqrt() / (255.0 * 3f64.sqrt())
+    }
+}
+
+/// Largest possible YIQ delta between two pixels.
+pub(crate) const MAX_YIQ_DELTA: f64 = 35215.0;
+
+pub(crate) fn blend(c: f64, a: f64) -> f64 {
+    255.0 + (c - 255.0) * a
+}
+
+pub(crate) fn rgb2y(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.29889531 + g * 0.58662247 + b * 0.11448223
+}
+
+fn rgb2i(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.59597799 - g * 0.27417610 - b * 0.32180189
+}
+
+fn rgb2q(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.21147017 - g * 0.52261711 + b * 0.31114694
+}
+
+// RGB channels blended over a white background
+fn blended_rgb(p: &Rgba<u8>) -> (f64, f64, f64) {
+    let a = p[3] as f64 / 255.0;
+    (blend(p[0] as f64, a), blend(p[1] as f64, a), blend(p[2] as f64, a))
+}
+
+/// Squared YIQ distance between two pixels blended over white.
+///
+/// The result is signed: negative when the second pixel is darker than the first.
+pub(crate) fn yiq_delta(p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+    if p1 == p2 {
+        return 0.0;
+    }
+
+    let (r1, g1, b1) = blended_rgb(p1);
+    let (r2, g2, b2) = blended_rgb(p2);
+
+    let y = rgb2y(r1, g1, b1) - rgb2y(r2, g2, b2);
+    let i = rgb2i(r1, g1, b1) - rgb2i(r2, g2, b2);
+    let q = rgb2q(r1, g1, b1) - rgb2q(r2, g2, b2);
+
+    let delta = 0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q;
+    if y > 0.0 { -delta } else { delta }
+}
+
+/// Signed brightness (Y) difference between two pixels blended over white.
+pub(crate) fn yiq_brightness_delta(p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+    if p1 == p2 {
+        return 0.0;
+    }
+
+    let (r1, g1, b1) = blended_rgb(p1);
+    let (r2, g2, b2) = blended_rgb(p2);
+    rgb2y(r1, g1, b1) - rgb2y(r2, g2, b2)
+}
+
+impl PixelMetric for Yiq {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        // sqrt keeps pixelmatch semantics: delta > 35215 * t^2  <=>  distance > t
+        (yiq_delta(p1, p2).abs() / MAX_YIQ_DELTA).sqrt()
+    }
+}
+
+impl PixelMetric for Exact {
+    fn distance(&self, p1: &Rgba<u8>, p2: &Rgba<u8>) -> f64 {
+        if p1 == p2 { 0.0 } else { 1.0 }
+    }
+}
+
+/// Built-in metrics selectable from the CLI and `CompareOptions`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    #[default]
+    Ciede2000,
+    Cie76,
+    Cie94,
+    Rgb,
+    Yiq,
+    Exact,
+}
+
+impl Metric {
+    pub fn pixel_metric(&self) -> &'static dyn PixelMetric {
+        match self {
+            Metric::Ciede2000 => &Ciede2000,
+            Metric::Cie76 => &Cie76,
+            Metric::Cie94 => &Cie94,
+            Metric::Rgb => &RgbEuclidean,
+            Metric::Yiq => &Yiq,
+            Metric::Exact => &Exact,
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ciede2000" | "de2000" => Ok(Metric::Ciede2000),
+            "cie76" | "de76" => Ok(Metric::Cie76),
+            "cie94" | "de94" => Ok(Metric::Cie94),
+            "rgb" => Ok(Metric::Rgb),
+            "yiq" => Ok(Metric::Yiq),
+            "exact" => Ok(Metric::Exact),
+            _ => Err(anyhow::anyhow!(
+                "Unknown metric '{}' (expected ciede2000, cie76, cie94, rgb, yiq or exact)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Metric::Ciede2000 => "ciede2000",
+            Metric::Cie76 => "cie76",
+            Metric::Cie94 => "cie94",
+            Metric::Rgb => "rgb",
+            Metric::Yiq => "yiq",
+            Metric::Exact => "exact",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn test_black_white_normalized() {
+        for metric in [Metric::Ciede2000, Metric::Cie76, Metric::Cie94, Metric::Rgb, Metric::Yiq, Metric::Exact] {
+            let d = metric.pixel_metric().distance(&BLACK, &WHITE);
+            assert!((d - 1.0).abs() < 0.1, "{} gave {}", metric, d);
+            assert_eq!(metric.pixel_metric().distance(&BLACK, &BLACK), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_exact_detects_single_step() {
+        let p = Rgba([100, 100, 100, 255]);
+        let q = Rgba([100, 100, 101, 255]);
+        assert_eq!(Exact.distance(&p, &q), 1.0);
+        assert!(Ciede2000.distance(&p, &q) < 0.01);
+    }
+
+    #[test]
+    fn test_metric_from_str() {
+        assert_eq!("CIE94".parse::<Metric>().unwrap(), Metric::Cie94);
+        assert_eq!(Metric::Yiq.to_string().parse::<Metric>().unwrap(), Metric::Yiq);
+        assert!("sobel".parse::<Metric>().is_err());
+    }
+
+    #[test]
+    fn test_lab_cache_collisions() {
+        // Many more colours than cache slots; every lookup must still match a fresh conversion
+        for i in 0..20_000u32 {
+            let [r, g, b, _] = (i.wrapping_mul(2_654_435_761)).to_le_bytes();
+            let p = Rgba([r, g, b, 255]);
+            assert_eq!(to_lab(&p), Lab::from_rgb(&[r, g, b]));
+            assert_eq!(to_lab(&p), Lab::from_rgb(&[r, g, b]));
+        }
+    }
+}
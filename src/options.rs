@@ -0,0 +1,416 @@
+use crate::alpha::AlphaMode;
+use crate::antialias::AntiAliasing;
+use crate::color::ColorManagement;
+use crate::compare::Region;
+use crate::error::{DiffError, Result};
+use crate::metric::Metric;
+use crate::precision::{Precision, Tolerance};
+use crate::render::DiffStyle;
+use crate::size::{Anchor, SizeMismatch};
+use crate::ssim::SsimOptions;
+use crate::svg::SvgOptions;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Settings shared by single-file and directory comparisons.
+///
+/// Serialized alongside results so a run can be reproduced from its JSON output.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CompareOptions {
+    /// Threshold for difference (0.0 to 1.0)
+    pub threshold: f32,
+    /// Channel precision of the comparison; native precision uses `tolerance` instead of `threshold`
+    pub precision: Precision,
+    /// Per-channel tolerance when comparing at native precision
+    pub tolerance: Tolerance,
+    /// Whether to render the diff overlay into `DiffResult::diff_image`
+    pub generate_diff: bool,
+    /// Whether to keep both images as compared (after size fitting and alignment) in `DiffResult::canvas`
+    pub generate_canvas: bool,
+    /// Regions excluded from the comparison
+    pub ignore_regions: Vec<Region>,
+    /// Mask image; black or mostly transparent pixels are excluded from the comparison
+    pub mask: Option<PathBuf>,
+    /// Per-pixel colour difference metric
+    pub metric: Metric,
+    /// Comparison algorithm
+    pub mode: CompareMode,
+    /// Anti-aliasing detection in standard mode; pixelmatch mode always uses full detection
+    pub anti_aliasing: AntiAliasing,
+    /// Settings used when `mode` is `CompareMode::Pixelmatch`
+    pub pixelmatch: PixelmatchOptions,
+    /// Structural similarity settings
+    pub ssim: SsimOptions,
+    /// Whether embedded ICC profiles are applied when loading files
+    pub color_management: ColorManagement,
+    /// How SVG inputs are rasterized before comparison
+    pub svg: SvgOptions,
+    /// Treatment of the alpha channel before pixels are compared
+    pub alpha: AlphaMode,
+    /// How images of different dimensions are brought onto a common canvas
+    pub size_mismatch: SizeMismatch,
+    /// Placement of the smaller image when padding or cropping
+    pub anchor: Anchor,
+    /// Search for a global translation of up to this many pixels and compare after shifting
+    pub align: Option<u32>,
+    /// Align rows of both images like a text diff before comparing, so inserted or removed
+    /// content does not shift the rest of the image out of place
+    pub align_rows: bool,
+    /// Outline each diff region in the diff image, with this much padding around it
+    pub boxes: Option<u32>,
+    /// Layout of the diff image
+    pub diff_style: DiffStyle,
+    /// Append a colour scale below heatmap diff images
+    pub legend: bool,
+    /// Compare every frame of animated and multi-page images; directory comparisons report
+    /// each frame as its own item
+    pub frames: bool,
+    /// Number of differing pixels a comparison may have before it fails; once exceeded, the
+    /// remaining rows are not scanned. `None` scans every row and fails on any difference
+    pub max_diff_pixels: Option<u64>,
+    /// Stop a directory comparison after this many files have failed
+    pub max_failures: Option<usize>,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        CompareOptions {
+            threshold: 0.1,
+            precision: Precision::default(),
+            tolerance: Tolerance::default(),
+            generate_diff: false,
+            generate_canvas: false,
+            ignore_regions: Vec::new(),
+            mask: None,
+            metric: Metric::default(),
+            mode: CompareMode::default(),
+            anti_aliasing: AntiAliasing::default(),
+            pixelmatch: PixelmatchOptions::default(),
+            ssim: SsimOptions::default(),
+            color_management: ColorManagement::default(),
+            svg: SvgOptions::default(),
+            alpha: AlphaMode::default(),
+            size_mismatch: SizeMismatch::default(),
+            anchor: Anchor::default(),
+            align: None,
+            align_rows: false,
+            boxes: None,
+            diff_style: DiffStyle::default(),
+            legend: false,
+            frames: false,
+            max_diff_pixels: None,
+            max_failures: None,
+        }
+    }
+}
+
+impl CompareOptions {
+    pub fn builder() -> CompareOptionsBuilder {
+        CompareOptionsBuilder::default()
+    }
+
+    /// Rejects settings the selected precision cannot honour.
+    ///
+    /// Native precision compares same-sized float buffers channel by channel against `tolerance`,
+    /// so it needs `SizeMismatch::Fail` and rejects any other metric, mode, anti-aliasing, alpha,
+    /// alignment or diff style setting, a changed `threshold`, and forced colour management.
+    /// At 8-bit precision `tolerance` must be zero, and the SVG DPI must always be positive. The
+    /// comparison functions call this first.
+    pub fn validate(&self) -> Result<()> {
+        if self.svg.dpi.is_nan() || self.svg.dpi <= 0.0 {
+            return Err(DiffError::InvalidOptions("svg dpi must be positive".to_string()));
+        }
+
+        let defaults = CompareOptions::default();
+        let unsupported = match self.precision {
+            Precision::Native => vec![
+                ("size_mismatch", self.size_mismatch != SizeMismatch::Fail),
+                ("threshold", self.threshold != defaults.threshold),
+                ("metric", self.metric != defaults.metric),
+                ("mode", self.mode != defaults.mode),
+                ("anti_aliasing", self.anti_aliasing != defaults.anti_aliasing),
+                ("color_management", self.color_management == ColorManagement::Force),
+                ("alpha", self.alpha != defaults.alpha),
+                ("align", self.align.is_some()),
+                ("align_rows", self.align_rows),
+                ("diff_style", self.diff_style != defaults.diff_style),
+            ],
+            Precision::EightBit => vec![("tolerance", self.tolerance != defaults.tolerance)],
+        };
+
+        let names: Vec<_> = unsupported.iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+        if names.is_empty() {
+            return Ok(());
+        }
+        let setting = if names.len() == 1 { "setting" } else { "settings" };
+        Err(DiffError::InvalidOptions(format!(
+            "{} {} cannot be used with {} precision",
+            names.join(", "),
+            setting,
+            self.precision
+        )))
+    }
+}
+
+#[derive(Default)]
+pub struct CompareOptionsBuilder {
+    options: CompareOptions,
+}
+
+impl CompareOptionsBuilder {
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.options.threshold = threshold;
+        self
+    }
+
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.options.precision = precision;
+        self
+    }
+
+    pub fn tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.options.tolerance = tolerance;
+        self
+    }
+
+    pub fn generate_diff(mut self, generate_diff: bool) -> Self {
+        self.options.generate_diff = generate_diff;
+        self
+    }
+
+    pub fn generate_canvas(mut self, generate_canvas: bool) -> Self {
+        self.options.generate_canvas = generate_canvas;
+        self
+    }
+
+    pub fn ignore_region(mut self, region: Region) -> Self {
+        self.options.ignore_regions.push(region);
+        self
+    }
+
+    pub fn ignore_regions(mut self, regions: impl IntoIterator<Item = Region>) -> Self {
+        self.options.ignore_regions.extend(regions);
+        self
+    }
+
+    pub fn mask(mut self, mask: Option<PathBuf>) -> Self {
+        self.options.mask = mask;
+        self
+    }
+
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.options.metric = metric;
+        self
+    }
+
+    pub fn mode(mut self, mode: CompareMode) -> Self {
+        self.options.mode = mode;
+        self
+    }
+
+    pub fn anti_aliasing(mut self, anti_aliasing: AntiAliasing) -> Self {
+        self.options.anti_aliasing = anti_aliasing;
+        self
+    }
+
+    pub fn pixelmatch(mut self, pixelmatch: PixelmatchOptions) -> Self {
+        self.options.pixelmatch = pixelmatch;
+        self
+    }
+
+    pub fn ssim(mut self, ssim: SsimOptions) -> Self {
+        self.options.ssim = ssim;
+        self
+    }
+
+    pub fn color_management(mut self, color_management: ColorManagement) -> Self {
+        self.options.color_management = color_management;
+        self
+    }
+
+    pub fn svg(mut self, svg: SvgOptions) -> Self {
+        self.options.svg = svg;
+        self
+    }
+
+    pub fn alpha(mut self, alpha: AlphaMode) -> Self {
+        self.options.alpha = alpha;
+        self
+    }
+
+    pub fn size_mismatch(mut self, size_mismatch: SizeMismatch) -> Self {
+        self.options.size_mismatch = size_mismatch;
+        self
+    }
+
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.options.anchor = anchor;
+        self
+    }
+
+    pub fn align(mut self, max_offset: Option<u32>) -> Self {
+        self.options.align = max_offset;
+        self
+    }
+
+    pub fn align_rows(mut self, align_rows: bool) -> Self {
+        self.options.align_rows = align_rows;
+        self
+    }
+
+    pub fn boxes(mut self, padding: Option<u32>) -> Self {
+        self.options.boxes = padding;
+        self
+    }
+
+    pub fn diff_style(mut self, diff_style: DiffStyle) -> Self {
+        self.options.diff_style = diff_style;
+        self
+    }
+
+    pub fn legend(mut self, legend: bool) -> Self {
+        self.options.legend = legend;
+        self
+    }
+
+    pub fn frames(mut self, frames: bool) -> Self {
+        self.options.frames = frames;
+        self
+    }
+
+    pub fn max_diff_pixels(mut self, max_diff_pixels: Option<u64>) -> Self {
+        self.options.max_diff_pixels = max_diff_pixels;
+        self
+    }
+
+    pub fn max_failures(mut self, max_failures: Option<usize>) -> Self {
+        self.options.max_failures = max_failures;
+        self
+    }
+
+    pub fn build(self) -> CompareOptions {
+        self.options
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareMode {
+    /// Per-pixel metric comparison with the anti-aliasing heuristic
+    #[default]
+    Standard,
+    /// Reproduces pixelmatch's YIQ delta, anti-aliasing detection and diff colours.
+    /// `metric` is ignored; `threshold` uses pixelmatch semantics.
+    Pixelmatch,
+}
+
+impl FromStr for CompareMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "standard" => Ok(CompareMode::Standard),
+            "pixelmatch" => Ok(CompareMode::Pixelmatch),
+            _ => Err(anyhow::anyhow!("Unknown mode '{}' (expected standard or pixelmatch)", s)),
+        }
+    }
+}
+
+impl fmt::Display for CompareMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareMode::Standard => f.write_str("standard"),
+            CompareMode::Pixelmatch => f.write_str("pixelmatch"),
+        }
+    }
+}
+
+/// An opaque RGB colour, parsed from `r,g,b` or `#rrggbb`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color(pub [u8; 3]);
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(anyhow::anyhow!("Hex colour must be in format #rrggbb"));
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            return Ok(Color([channel(0)?, channel(2)?, channel(4)?]));
+        }
+
+        let parts: Vec<u8> = s.split(',')
+            .map(|p| p.trim().parse::<u8>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if parts.len() != 3 {
+            return Err(anyhow::anyhow!("Colour must be in format r,g,b or #rrggbb"));
+        }
+        Ok(Color([parts[0], parts[1], parts[2]]))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+/// pixelmatch's output options, with the same defaults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PixelmatchOptions {
+    /// Count anti-aliased pixels as differences instead of excluding them
+    pub include_aa: bool,
+    /// Opacity of the faded original image in the diff output
+    pub alpha: f32,
+    /// Colour of anti-aliased pixels in the diff output
+    pub aa_color: Color,
+    /// Colour of differing pixels in the diff output
+    pub diff_color: Color,
+    /// Colour of differing pixels that are darker in the second image; defaults to `diff_color`
+    pub diff_color_alt: Option<Color>,
+}
+
+impl Default for PixelmatchOptions {
+    fn default() -> Self {
+        PixelmatchOptions {
+            include_aa: false,
+            alpha: 0.1,
+            aa_color: Color([255, 255, 0]),
+            diff_color: Color([255, 0, 0]),
+            diff_color_alt: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_and_defaults() {
+        let options = CompareOptions::builder()
+            .threshold(0.25)
+            .ignore_region(Region { x: 0, y: 0, width: 5, height: 5 })
+            .build();
+        assert_eq!(options.threshold, 0.25);
+        assert_eq!(options.ignore_regions.len(), 1);
+        assert!(!options.generate_diff);
+
+        // Missing fields fall back to defaults so saved options stay loadable
+        let parsed: CompareOptions = serde_json::from_str(r#"{"threshold": 0.3}"#).unwrap();
+        assert_eq!(parsed.threshold, 0.3);
+        assert!(parsed.mask.is_none());
+    }
+
+    #[test]
+    fn test_color_from_str() {
+        assert_eq!("255,0,128".parse::<Color>().unwrap(), Color([255, 0, 128]));
+        assert_eq!("#ff0080".parse::<Color>().unwrap(), Color([255, 0, 128]));
+        assert!("255,0".parse::<Color>().is_err());
+        assert!("#ff00".parse::<Color>().is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! A port of [pixelmatch](https://github.com/mapbox/pixelmatch)'s comparison loop.
+//!
+//! Thresholds, anti-aliasing detection and diff colours follow the JavaScript
+//! implementation so suites tuned on pixelmatch report the same diff counts.
+
+use crate::antialias::antialiased;
+use crate::compare::{par_rows, DiffPass, PixelOutcome};
+use crate::metric::{blend, rgb2y, yiq_delta, MAX_YIQ_DELTA};
+use crate::options::{Color, PixelmatchOptions};
+use image::{Rgba, RgbaImage};
+
+pub(crate) fn diff(
+    img_a: &RgbaImage,
+    img_b: &RgbaImage,
+    threshold: f32,
+    options: &PixelmatchOptions,
+    is_ignored: impl Fn(u32, u32) -> bool + Sync,
+    output: Option<&mut RgbaImage>,
+    limit: Option<u64>,
+) -> DiffPass {
+    let max_delta = MAX_YIQ_DELTA * (threshold as f64) * (threshold as f64);
+    let diff_color_alt = options.diff_color_alt.unwrap_or(options.diff_color);
+
+    par_rows(img_a, img_b, output, limit, |x, y, identical_row| {
+        let pixel_a = img_a.get_pixel(x, y);
+
+        let delta = if identical_row || is_ignored(x, y) { 0.0 } else { yiq_delta(pixel_a, img_b.get_pixel(x, y)) };
+        // Same scale as the YIQ metric
+        let distance = (delta.abs() / MAX_YIQ_DELTA).sqrt() as f32;
+
+        if delta.abs() > max_delta {
+            let is_excluded_aa =
+                !options.include_aa && (antialiased(img_a, img_b, x, y) || antialiased(img_b, img_a, x, y));
+
+            if is_excluded_aa {
+                (distance, PixelOutcome::AntiAliased, opaque(options.aa_color))
+            } else {
+                let color = if delta < 0.0 { diff_color_alt } else { options.diff_color };
+                (distance, PixelOutcome::Different, opaque(color))
+            }
+        } else {
+            (distance, PixelOutcome::Same, gray_pixel(pixel_a, options.alpha as f64))
+        }
+    })
+}
+
+fn opaque(color: Color) -> Rgba<u8> {
+    let [r, g, b] = color.0;
+    Rgba([r, g, b, 255])
+}
+
+fn gray_pixel(pixel: &Rgba<u8>, alpha: f64) -> Rgba<u8> {
+    let luma = rgb2y(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+    // Uint8ClampedArray rounds half to even
+    let val = blend(luma, alpha * pixel[3] as f64 / 255.0).round_ties_even().clamp(0.0, 255.0) as u8;
+    Rgba([val, val, val, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antialias::tests::edge_pair;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let img = solid(8, 8, [120, 80, 40, 255]);
+        let mut output = solid(8, 8, [0, 0, 0, 0]);
+        let pass = diff(&img, &img, 0.1, &PixelmatchOptions::default(), |_, _| false, Some(&mut output), None);
+        assert_eq!(pass.diff_pixels, 0);
+        // Unchanged pixels are drawn as faded grayscale over white
+        let p = output.get_pixel(0, 0);
+        assert_eq!(p[0], p[1]);
+        assert!(p[0] > 200);
+    }
+
+    #[test]
+    fn test_diff_colors() {
+        let img_a = solid(8, 8, [255, 255, 255, 255]);
+        let mut img_b = img_a.clone();
+        img_b.put_pixel(4, 4, Rgba([0, 0, 0, 255]));
+
+        let options = PixelmatchOptions { diff_color_alt: Some(Color([0, 255, 0])), ..Default::default() };
+        let mut output = solid(8, 8, [0, 0, 0, 0]);
+        let pass = diff(&img_a, &img_b, 0.1, &options, |_, _| false, Some(&mut output), None);
+        assert_eq!(pass.diff_pixels, 1);
+        // B got darker, so the alternative colour is used
+        assert_eq!(*output.get_pixel(4, 4), Rgba([0, 255, 0, 255]));
+
+        let pass = diff(&img_b, &img_a, 0.1, &options, |_, _| false, Some(&mut output), None);
+        assert_eq!(pass.diff_pixels, 1);
+        assert_eq!(*output.get_pixel(4, 4), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_antialiased_edge_excluded() {
+        let (img_a, img_b) = edge_pair();
+
+        let pass = diff(&img_a, &img_b, 0.1, &PixelmatchOptions::default(), |_, _| false, None, None);
+        assert_eq!((pass.diff_pixels, pass.aa_pixels), (0, 8));
+
+        let include_aa = PixelmatchOptions { include_aa: true, ..Default::default() };
+        let pass = diff(&img_a, &img_b, 0.1, &include_aa, |_, _| false, None, None);
+        assert_eq!((pass.diff_pixels, pass.aa_pixels), (8, 0));
+    }
+}
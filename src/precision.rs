@@ -0,0 +1,141 @@
+//! Comparison of 16-bit and floating-point images at their native precision.
+//!
+//! The 8-bit pipeline quantises every input with `to_rgba8()`, which hides small changes in
+//! depth maps and linear HDR renders. Native comparison works on `f32` channels instead and
+//! decides per channel with absolute and relative tolerances rather than a perceptual metric.
+
+use crate::compare::{par_rows, DiffPass, PixelOutcome, DIFF_COLOR};
+use image::{ImageBuffer, Rgba, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A 16-bit RGBA image, used for native-precision diff output.
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Channel precision at which images are compared.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Precision {
+    /// Quantise to 8 bits per channel and use the configured metric and threshold
+    #[default]
+    #[serde(rename = "8bit")]
+    EightBit,
+    /// Compare 16-bit and float data as stored, using `CompareOptions::tolerance`
+    Native,
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "8bit" | "8" => Ok(Precision::EightBit),
+            "native" => Ok(Precision::Native),
+            _ => Err(anyhow::anyhow!("Unknown precision '{}' (expected 8bit or native)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Precision::EightBit => f.write_str("8bit"),
+            Precision::Native => f.write_str("native"),
+        }
+    }
+}
+
+/// Per-channel tolerance of a native-precision comparison.
+///
+/// Channels are normalised so 16-bit values span 0.0-1.0; float data is used as stored.
+/// Two values match when `|a - b| <= absolute + relative * max(|a|, |b|)`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Tolerance {
+    pub absolute: f32,
+    pub relative: f32,
+}
+
+impl Tolerance {
+    /// Whether two channel values are within tolerance. NaN only matches NaN.
+    pub fn accepts(&self, a: f32, b: f32) -> bool {
+        if a.is_nan() || b.is_nan() {
+            return a.is_nan() && b.is_nan();
+        }
+        // Also covers equal infinities, whose difference is NaN
+        if a == b {
+            return true;
+        }
+        (a - b).abs() <= self.absolute + self.relative * a.abs().max(b.abs())
+    }
+}
+
+fn to_u16(c: f32) -> u16 {
+    (c.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+/// Compares same-sized float images channel by channel.
+///
+/// Distances are the largest absolute channel difference, capped at 1.0.
+pub(crate) fn diff(
+    img_a: &Rgba32FImage,
+    img_b: &Rgba32FImage,
+    tolerance: Tolerance,
+    is_ignored: impl Fn(u32, u32) -> bool + Sync,
+    diff_buffer: Option<&mut Rgba16Image>,
+    limit: Option<u64>,
+) -> DiffPass {
+    let diff_color = Rgba(DIFF_COLOR.0.map(|c| c as u16 * 257));
+
+    par_rows(img_a, img_b, diff_buffer, limit, |x, y, identical_row| {
+        let pixel_a = img_a.get_pixel(x, y);
+        let pixel_b = img_b.get_pixel(x, y);
+        let ignored = is_ignored(x, y);
+
+        let mut different = false;
+        let mut dist = 0.0f32;
+        if !ignored && !identical_row {
+            for (&a, &b) in pixel_a.0.iter().zip(&pixel_b.0) {
+                // Identical NaN or infinite channels have no meaningful difference
+                if a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()) {
+                    continue;
+                }
+                different |= !tolerance.accepts(a, b);
+                let d = (a - b).abs();
+                dist = dist.max(if d.is_nan() { 1.0 } else { d.min(1.0) });
+            }
+        }
+
+        if different {
+            (dist, PixelOutcome::Different, diff_color)
+        } else {
+            let factor = if ignored { 0.02 } else { 0.1 };
+            let dim = |c: f32| to_u16(c * factor);
+            (dist, PixelOutcome::Same, Rgba([dim(pixel_a[0]), dim(pixel_a[1]), dim(pixel_a[2]), u16::MAX]))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerance() {
+        let exact = Tolerance::default();
+        assert!(exact.accepts(0.5, 0.5));
+        assert!(!exact.accepts(0.5, 0.5 + 1.0 / 65535.0));
+        assert!(exact.accepts(f32::INFINITY, f32::INFINITY));
+        assert!(exact.accepts(f32::NAN, f32::NAN));
+        assert!(!exact.accepts(f32::NAN, 0.0));
+
+        let absolute = Tolerance { absolute: 0.01, relative: 0.0 };
+        assert!(absolute.accepts(0.0, 0.009));
+        assert!(!absolute.accepts(100.0, 100.5));
+
+        // Relative tolerance scales with magnitude, as suits linear HDR values
+        let relative = Tolerance { absolute: 0.0, relative: 0.01 };
+        assert!(relative.accepts(100.0, 100.5));
+        assert!(!relative.accepts(0.001, 0.002));
+    }
+}
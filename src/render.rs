@@ -0,0 +1,250 @@
+//! Drawing helpers for diff images.
+
+use crate::colormap;
+use crate::compare::{DiffPass, Region, DIFF_COLOR};
+use image::{imageops, ImageBuffer, Pixel, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Layout of the diff image.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiffStyle {
+    /// The first image dimmed, with differing pixels highlighted
+    #[default]
+    Overlay,
+    /// The two images next to each other
+    SideBySide,
+    /// The two images followed by the overlay
+    ThreeUp,
+    /// A 50% alpha blend of the two images
+    Blend,
+    /// The second image with differing pixels highlighted
+    Onion,
+    /// Distance of every pixel mapped through a heat ramp; differences under the threshold are faint
+    Heatmap,
+}
+
+impl DiffStyle {
+    /// Number of canvas-sized panels laid out left to right.
+    pub fn panels(&self) -> u32 {
+        match self {
+            DiffStyle::SideBySide => 2,
+            DiffStyle::ThreeUp => 3,
+            DiffStyle::Overlay | DiffStyle::Blend | DiffStyle::Onion | DiffStyle::Heatmap => 1,
+        }
+    }
+}
+
+impl FromStr for DiffStyle {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "overlay" => Ok(DiffStyle::Overlay),
+            "side-by-side" => Ok(DiffStyle::SideBySide),
+            "three-up" => Ok(DiffStyle::ThreeUp),
+            "blend" => Ok(DiffStyle::Blend),
+            "onion" => Ok(DiffStyle::Onion),
+            "heatmap" => Ok(DiffStyle::Heatmap),
+            _ => Err(anyhow::anyhow!(
+                "Unknown diff style '{}' (expected overlay, side-by-side, three-up, blend, onion or heatmap)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DiffStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DiffStyle::Overlay => "overlay",
+            DiffStyle::SideBySide => "side-by-side",
+            DiffStyle::ThreeUp => "three-up",
+            DiffStyle::Blend => "blend",
+            DiffStyle::Onion => "onion",
+            DiffStyle::Heatmap => "heatmap",
+        };
+        f.write_str(name)
+    }
+}
+
+// Opacity of the heat colour for pixels that differ but stay under the threshold
+const NEAR_MISS_OPACITY: f32 = 0.35;
+// Legend bar height and the gap above it
+const LEGEND_HEIGHT: u32 = 12;
+const LEGEND_GAP: u32 = 4;
+
+/// Position of `distance` on the heat ramp.
+///
+/// The square root spreads out small distances, which are the interesting ones.
+pub fn heat_position(distance: f32) -> f32 {
+    distance.max(0.0).sqrt().min(1.0)
+}
+
+fn mix(p: &Rgba<u8>, q: &Rgba<u8>, t: f32) -> Rgba<u8> {
+    let c = |i: usize| (p[i] as f32 * (1.0 - t) + q[i] as f32 * t).round() as u8;
+    Rgba([c(0), c(1), c(2), c(3)])
+}
+
+/// Lays out the diff image for `style` from the canvas images and the rendered overlay.
+pub(crate) fn compose(
+    style: DiffStyle,
+    img_a: &RgbaImage,
+    img_b: &RgbaImage,
+    overlay: RgbaImage,
+    pass: &DiffPass,
+) -> RgbaImage {
+    let (width, height) = img_a.dimensions();
+
+    match style {
+        DiffStyle::Overlay => overlay,
+        DiffStyle::SideBySide | DiffStyle::ThreeUp => {
+            let mut out = RgbaImage::new(width * style.panels(), height);
+            imageops::replace(&mut out, img_a, 0, 0);
+            imageops::replace(&mut out, img_b, width as i64, 0);
+            if style == DiffStyle::ThreeUp {
+                imageops::replace(&mut out, &overlay, 2 * width as i64, 0);
+            }
+            out
+        }
+        DiffStyle::Blend => {
+            RgbaImage::from_fn(width, height, |x, y| mix(img_a.get_pixel(x, y), img_b.get_pixel(x, y), 0.5))
+        }
+        DiffStyle::Onion => RgbaImage::from_fn(width, height, |x, y| {
+            let pixel = *img_b.get_pixel(x, y);
+            if pass.different[(y * width + x) as usize] {
+                mix(&Rgba([pixel[0], pixel[1], pixel[2], 255]), &DIFF_COLOR, 0.6)
+            } else {
+                pixel
+            }
+        }),
+        DiffStyle::Heatmap => RgbaImage::from_fn(width, height, |x, y| {
+            let i = (y * width + x) as usize;
+            let a = img_a.get_pixel(x, y);
+            let dimmed = Rgba([a[0] / 10, a[1] / 10, a[2] / 10, 255]);
+            let distance = pass.distances[i];
+            if distance <= 0.0 {
+                return dimmed;
+            }
+
+            let heat = colormap::heat(heat_position(distance));
+            if pass.different[i] {
+                heat
+            } else {
+                mix(&dimmed, &heat, NEAR_MISS_OPACITY)
+            }
+        }),
+    }
+}
+
+/// Appends a heat ramp bar below `img`, with a white marker at `threshold`.
+pub(crate) fn append_legend(img: &RgbaImage, threshold: f32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let mut out = RgbaImage::new(width, height + LEGEND_GAP + LEGEND_HEIGHT);
+    imageops::replace(&mut out, img, 0, 0);
+
+    let span = width.saturating_sub(1).max(1) as f32;
+    let marker = (heat_position(threshold) * span).round() as u32;
+    for x in 0..width {
+        let color = if x == marker { Rgba([255, 255, 255, 255]) } else { colormap::heat(x as f32 / span) };
+        for y in height + LEGEND_GAP..out.height() {
+            out.put_pixel(x, y, color);
+        }
+    }
+    out
+}
+
+/// Grows `region` by `padding` on every side, clamped to a `width` x `height` image.
+pub fn padded_box(region: &Region, padding: u32, width: u32, height: u32) -> Region {
+    let x = region.x.saturating_sub(padding);
+    let y = region.y.saturating_sub(padding);
+    let right = (region.x + region.width).saturating_add(padding).min(width);
+    let bottom = (region.y + region.height).saturating_add(padding).min(height);
+    Region { x, y, width: right.saturating_sub(x), height: bottom.saturating_sub(y) }
+}
+
+/// Outline thickness that stays visible on large images: 1px up to 500px, growing from there.
+pub fn box_thickness(width: u32, height: u32) -> u32 {
+    (width.max(height) / 500).max(1)
+}
+
+/// Draws the outline of `region` with lines `thickness` pixels wide, inside the region.
+pub fn draw_box<P: Pixel>(img: &mut ImageBuffer<P, Vec<P::Subpixel>>, region: &Region, thickness: u32, color: P) {
+    let right = (region.x + region.width).min(img.width());
+    let bottom = (region.y + region.height).min(img.height());
+    if region.x >= right || region.y >= bottom {
+        return;
+    }
+
+    for y in region.y..bottom {
+        for x in region.x..right {
+            let on_edge = x < region.x + thickness
+                || y < region.y + thickness
+                || x + thickness >= right
+                || y + thickness >= bottom;
+            if on_edge {
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padded_box_outline() {
+        let region = Region { x: 1, y: 5, width: 2, height: 2 };
+        let padded = padded_box(&region, 2, 8, 8);
+        assert_eq!((padded.x, padded.y, padded.width, padded.height), (0, 3, 5, 5));
+
+        let red = Rgba([255, 0, 0, 255]);
+        let mut img = RgbaImage::new(8, 8);
+        draw_box(&mut img, &padded, 1, red);
+        assert_eq!(*img.get_pixel(0, 3), red);
+        assert_eq!(*img.get_pixel(4, 7), red);
+        assert_eq!(*img.get_pixel(2, 5), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_compose_layouts() {
+        let img_a = RgbaImage::from_pixel(4, 3, Rgba([0, 0, 0, 255]));
+        let img_b = RgbaImage::from_pixel(4, 3, Rgba([200, 100, 0, 255]));
+        let overlay = RgbaImage::from_pixel(4, 3, DIFF_COLOR);
+        let mut pass = DiffPass::new(4, 3);
+        pass.record(1, 1, 1.0);
+        pass.mark_different(1, 1);
+
+        let out = compose(DiffStyle::ThreeUp, &img_a, &img_b, overlay.clone(), &pass);
+        assert_eq!(out.dimensions(), (12, 3));
+        assert_eq!(*out.get_pixel(5, 0), Rgba([200, 100, 0, 255]));
+        assert_eq!(*out.get_pixel(9, 0), DIFF_COLOR);
+
+        let out = compose(DiffStyle::Blend, &img_a, &img_b, overlay.clone(), &pass);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([100, 50, 0, 255]));
+
+        let out = compose(DiffStyle::Onion, &img_a, &img_b, overlay, &pass);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([200, 100, 0, 255]));
+        assert_ne!(*out.get_pixel(1, 1), Rgba([200, 100, 0, 255]));
+    }
+
+    #[test]
+    fn test_heatmap_near_miss_is_faint() {
+        let img = RgbaImage::from_pixel(3, 1, Rgba([0, 0, 0, 255]));
+        let mut pass = DiffPass::new(3, 1);
+        pass.record(1, 0, 0.25);
+        pass.record(2, 0, 0.25);
+        pass.mark_different(2, 0);
+
+        let out = compose(DiffStyle::Heatmap, &img, &img, img.clone(), &pass);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(2, 0), colormap::heat(0.5));
+        assert!(out.get_pixel(1, 0)[1] < out.get_pixel(2, 0)[1]);
+
+        let with_legend = append_legend(&out, 0.25);
+        assert_eq!(with_legend.height(), 1 + LEGEND_GAP + LEGEND_HEIGHT);
+        assert_eq!(*with_legend.get_pixel(1, 1 + LEGEND_GAP), Rgba([255, 255, 255, 255]));
+    }
+}
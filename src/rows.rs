@@ -0,0 +1,300 @@
+//! Scanline alignment for pages with inserted or removed content.
+//!
+//! Rows of both images are hashed and aligned with Myers' diff algorithm, like a text diff
+//! over scanlines. Matching rows are then compared pixel by pixel as usual, while rows that
+//! exist in only one image are reported as inserted or removed bands.
+
+use crate::compare::Region;
+use crate::error::Result;
+use crate::size::{self, Anchor, Canvas, SizeMismatch};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+// Beyond this many inserted plus removed rows the images are treated as unrelated
+const MAX_EDIT_ROWS: usize = 2000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RowBandKind {
+    /// Rows present only in the second image
+    Inserted,
+    /// Rows present only in the first image
+    Removed,
+    /// Rows present in both images at the same aligned position, but with different content
+    Changed,
+}
+
+impl fmt::Display for RowBandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowBandKind::Inserted => f.write_str("inserted"),
+            RowBandKind::Removed => f.write_str("removed"),
+            RowBandKind::Changed => f.write_str("changed"),
+        }
+    }
+}
+
+/// A run of rows that did not match exactly between the two images.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RowBand {
+    pub kind: RowBandKind,
+    /// First row in the first image; for insertions, the row the band was inserted before
+    pub a_start: u32,
+    pub a_rows: u32,
+    /// First row in the second image; for removals, the row the band was removed before
+    pub b_start: u32,
+    pub b_rows: u32,
+    /// First row of the band in the aligned canvas and diff image
+    pub y: u32,
+    pub height: u32,
+}
+
+/// Source rows of every canvas row, plus the bands that did not match.
+pub(crate) struct RowAlignment {
+    pub rows: Vec<(Option<u32>, Option<u32>)>,
+    pub bands: Vec<RowBand>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Remove,
+    Insert,
+}
+
+fn row_hashes(img: &RgbaImage) -> Vec<u64> {
+    let stride = img.width() as usize * 4;
+    if stride == 0 {
+        return vec![0; img.height() as usize];
+    }
+    img.as_raw()
+        .chunks_exact(stride)
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Shortest edit script turning `a` into `b`, or `None` if it needs more than `max_d` edits.
+fn myers(a: &[u64], b: &[u64], max_d: usize) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = max_d.min(a.len() + b.len());
+    let offset = max_d as isize + 1;
+    let mut v = vec![0isize; 2 * max_d + 3];
+    let mut trace = Vec::new();
+
+    let next_x = |v: &[isize], d: isize, k: isize| {
+        if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+            v[(offset + k + 1) as usize]
+        } else {
+            v[(offset + k - 1) as usize] + 1
+        }
+    };
+
+    for d in 0..=max_d as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = next_x(&v, d, k);
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m, offset));
+            }
+        }
+    }
+
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize, offset: isize) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(offset + prev_k) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == prev_x { Edit::Insert } else { Edit::Remove });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Groups an edit script into canvas rows and bands.
+///
+/// Within each run of edits, removed and inserted rows are paired up as changed rows first;
+/// whatever is left over becomes a removed or inserted band.
+fn build_alignment(edits: &[Edit]) -> RowAlignment {
+    let mut rows = Vec::with_capacity(edits.len());
+    let mut bands = Vec::new();
+    let (mut a, mut b) = (0u32, 0u32);
+    let mut i = 0;
+
+    while i < edits.len() {
+        if edits[i] == Edit::Equal {
+            rows.push((Some(a), Some(b)));
+            a += 1;
+            b += 1;
+            i += 1;
+            continue;
+        }
+
+        let run_end = edits[i..].iter().position(|e| *e == Edit::Equal).map_or(edits.len(), |p| i + p);
+        let removed = edits[i..run_end].iter().filter(|e| **e == Edit::Remove).count() as u32;
+        let inserted = (run_end - i) as u32 - removed;
+        let changed = removed.min(inserted);
+
+        let mut push_band = |kind, a_rows: u32, b_rows: u32, rows: &mut Vec<(Option<u32>, Option<u32>)>| {
+            let y = rows.len() as u32;
+            let height = a_rows.max(b_rows);
+            for r in 0..height {
+                let row_a = (r < a_rows).then_some(a + r);
+                let row_b = (r < b_rows).then_some(b + r);
+                rows.push((row_a, row_b));
+            }
+            bands.push(RowBand { kind, a_start: a, a_rows, b_start: b, b_rows, y, height });
+            a += a_rows;
+            b += b_rows;
+        };
+
+        if changed > 0 {
+            push_band(RowBandKind::Changed, changed, changed, &mut rows);
+        }
+        if removed > changed {
+            push_band(RowBandKind::Removed, removed - changed, 0, &mut rows);
+        }
+        if inserted > changed {
+            push_band(RowBandKind::Inserted, 0, inserted - changed, &mut rows);
+        }
+
+        i = run_end;
+    }
+
+    RowAlignment { rows, bands }
+}
+
+fn copy_row(dst: &mut RgbaImage, dst_y: u32, src: &RgbaImage, src_y: u32) {
+    let stride = src.width() as usize * 4;
+    let (dst_start, src_start) = (dst_y as usize * stride, src_y as usize * stride);
+    let dst: &mut [u8] = dst;
+    dst[dst_start..dst_start + stride].copy_from_slice(&src.as_raw()[src_start..src_start + stride]);
+}
+
+/// Aligns the rows of both images onto a shared canvas.
+///
+/// Widths are reconciled first according to `policy`; heights may differ freely. Canvas rows
+/// that exist in only one image are transparent in the other.
+pub(crate) fn align<'a>(
+    img_a: &'a RgbaImage,
+    img_b: &'a RgbaImage,
+    policy: SizeMismatch,
+    anchor: Anchor,
+) -> Result<(Canvas<'a>, RowAlignment)> {
+    let ((fit_a, footprint_a, origin_a), (fit_b, footprint_b, _)) = size::fit_width(img_a, img_b, policy, anchor)?;
+
+    let (hashes_a, hashes_b) = (row_hashes(&fit_a), row_hashes(&fit_b));
+    let edits = myers(&hashes_a, &hashes_b, MAX_EDIT_ROWS).unwrap_or_else(|| {
+        // Too different to align: compare top to bottom like a plain diff
+        let mut edits = vec![Edit::Remove; hashes_a.len()];
+        edits.extend(std::iter::repeat_n(Edit::Insert, hashes_b.len()));
+        edits
+    });
+    let alignment = build_alignment(&edits);
+
+    let width = fit_a.width();
+    let height = alignment.rows.len() as u32;
+    let mut aligned_a = RgbaImage::new(width, height);
+    let mut aligned_b = RgbaImage::new(width, height);
+    for (y, (row_a, row_b)) in alignment.rows.iter().enumerate() {
+        if let Some(row_a) = row_a {
+            copy_row(&mut aligned_a, y as u32, &fit_a, *row_a);
+        }
+        if let Some(row_b) = row_b {
+            copy_row(&mut aligned_b, y as u32, &fit_b, *row_b);
+        }
+    }
+
+    let canvas = Canvas {
+        a: Cow::Owned(aligned_a),
+        b: Cow::Owned(aligned_b),
+        footprint_a: Region { y: 0, height, ..footprint_a },
+        footprint_b: Region { y: 0, height, ..footprint_b },
+        origin_a,
+    };
+    Ok((canvas, alignment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn page(rows: &[u8]) -> RgbaImage {
+        RgbaImage::from_fn(4, rows.len() as u32, |_, y| {
+            let v = rows[y as usize];
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    #[test]
+    fn test_inserted_banner() {
+        let img_a = page(&[1, 2, 3, 4, 5]);
+        let img_b = page(&[1, 9, 9, 2, 3, 4, 5]);
+
+        let (canvas, alignment) = align(&img_a, &img_b, SizeMismatch::Pad, Anchor::TopLeft).unwrap();
+        assert_eq!(canvas.a.height(), 7);
+        assert_eq!(
+            alignment.bands,
+            vec![RowBand { kind: RowBandKind::Inserted, a_start: 1, a_rows: 0, b_start: 1, b_rows: 2, y: 1, height: 2 }]
+        );
+        assert_eq!(alignment.rows[3], (Some(1), Some(3)));
+        assert_eq!(*canvas.a.get_pixel(0, 1), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_changed_and_removed() {
+        let img_a = page(&[1, 2, 3, 4, 5]);
+        let img_b = page(&[1, 7, 5]);
+
+        let (_, alignment) = align(&img_a, &img_b, SizeMismatch::Pad, Anchor::TopLeft).unwrap();
+        let kinds: Vec<_> = alignment.bands.iter().map(|b| (b.kind, b.a_rows, b.b_rows)).collect();
+        assert_eq!(kinds, vec![(RowBandKind::Changed, 1, 1), (RowBandKind::Removed, 2, 0)]);
+        assert_eq!(alignment.rows.len(), 5);
+    }
+
+    #[test]
+    fn test_myers_gives_up() {
+        let a: Vec<u64> = (0..10).collect();
+        let b: Vec<u64> = (10..20).collect();
+        assert!(myers(&a, &b, 5).is_none());
+        assert_eq!(myers(&a, &b, 20).unwrap().len(), 20);
+    }
+}
@@ -0,0 +1,306 @@
+//! Bringing two images of different sizes onto a common canvas.
+
+use crate::compare::Region;
+use crate::error::{DiffError, Result};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// What to do when the two images have different dimensions.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizeMismatch {
+    /// Return `DiffError::DimensionMismatch`
+    Fail,
+    /// Place both images on a canvas of the larger dimensions, filling the rest with transparent black
+    #[default]
+    Pad,
+    /// Compare only the overlapping area of the smaller dimensions
+    Crop,
+    /// Scale the second image to the dimensions of the first
+    Resize,
+}
+
+impl FromStr for SizeMismatch {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fail" => Ok(SizeMismatch::Fail),
+            "pad" => Ok(SizeMismatch::Pad),
+            "crop" => Ok(SizeMismatch::Crop),
+            "resize" => Ok(SizeMismatch::Resize),
+            _ => Err(anyhow::anyhow!("Unknown size mismatch policy '{}' (expected fail, pad, crop or resize)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeMismatch::Fail => f.write_str("fail"),
+            SizeMismatch::Pad => f.write_str("pad"),
+            SizeMismatch::Crop => f.write_str("crop"),
+            SizeMismatch::Resize => f.write_str("resize"),
+        }
+    }
+}
+
+/// Where the smaller image sits on the canvas when padding or cropping.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+const ANCHOR_NAMES: [(Anchor, &str); 9] = [
+    (Anchor::TopLeft, "top-left"),
+    (Anchor::Top, "top"),
+    (Anchor::TopRight, "top-right"),
+    (Anchor::Left, "left"),
+    (Anchor::Center, "center"),
+    (Anchor::Right, "right"),
+    (Anchor::BottomLeft, "bottom-left"),
+    (Anchor::Bottom, "bottom"),
+    (Anchor::BottomRight, "bottom-right"),
+];
+
+impl FromStr for Anchor {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        ANCHOR_NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(anchor, _)| *anchor)
+            .ok_or_else(|| anyhow::anyhow!("Unknown anchor '{}' (expected e.g. top-left, center, bottom-right)", s))
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = ANCHOR_NAMES.iter().find(|(anchor, _)| anchor == self).map_or("", |(_, name)| name);
+        f.write_str(name)
+    }
+}
+
+impl Anchor {
+    /// Offset of an `inner`-sized box placed inside an `outer`-sized box.
+    fn offset(&self, outer: (u32, u32), inner: (u32, u32)) -> (u32, u32) {
+        let free_x = outer.0.saturating_sub(inner.0);
+        let free_y = outer.1.saturating_sub(inner.1);
+
+        let x = match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => free_x / 2,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => free_x,
+        };
+        let y = match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0,
+            Anchor::Left | Anchor::Center | Anchor::Right => free_y / 2,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => free_y,
+        };
+        (x, y)
+    }
+}
+
+/// Both images on a shared canvas, plus where each original lies on it.
+pub(crate) struct Canvas<'a> {
+    pub a: Cow<'a, RgbaImage>,
+    pub b: Cow<'a, RgbaImage>,
+    /// Canvas area covered by the first image
+    pub footprint_a: Region,
+    /// Canvas area covered by the second image
+    pub footprint_b: Region,
+    /// Pixel of the first image shown at the top-left corner of `footprint_a`; non-zero when
+    /// the first image was cropped
+    pub origin_a: (u32, u32),
+}
+
+impl Canvas<'_> {
+    /// Position in the first image of a canvas pixel, or `None` where the first image does not
+    /// cover the canvas.
+    pub fn source_a(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let fp = &self.footprint_a;
+        fp.contains(x, y).then(|| (x - fp.x + self.origin_a.0, y - fp.y + self.origin_a.1))
+    }
+
+    /// Whether the pixel lies outside either original image.
+    pub fn is_padding(&self, x: u32, y: u32) -> bool {
+        !self.footprint_a.contains(x, y) || !self.footprint_b.contains(x, y)
+    }
+
+    pub fn has_padding(&self) -> bool {
+        let full = (self.a.width(), self.a.height());
+        (self.footprint_a.width, self.footprint_a.height) != full
+            || (self.footprint_b.width, self.footprint_b.height) != full
+    }
+}
+
+pub(crate) fn fit<'a>(
+    img_a: &'a RgbaImage,
+    img_b: &'a RgbaImage,
+    policy: SizeMismatch,
+    anchor: Anchor,
+) -> Result<Canvas<'a>> {
+    let dims_a = img_a.dimensions();
+    let dims_b = img_b.dimensions();
+
+    if dims_a == dims_b {
+        let full = Region { x: 0, y: 0, width: dims_a.0, height: dims_a.1 };
+        return Ok(Canvas {
+            a: Cow::Borrowed(img_a),
+            b: Cow::Borrowed(img_b),
+            footprint_a: full.clone(),
+            footprint_b: full,
+            origin_a: (0, 0),
+        });
+    }
+
+    match policy {
+        SizeMismatch::Fail => Err(DiffError::DimensionMismatch { a: dims_a, b: dims_b }),
+        SizeMismatch::Pad => {
+            let size = (dims_a.0.max(dims_b.0), dims_a.1.max(dims_b.1));
+            let (a, footprint_a, origin_a) = pad(img_a, size, anchor);
+            let (b, footprint_b, _) = pad(img_b, size, anchor);
+            Ok(Canvas { a, b, footprint_a, footprint_b, origin_a })
+        }
+        SizeMismatch::Crop => {
+            let size = (dims_a.0.min(dims_b.0), dims_a.1.min(dims_b.1));
+            let (a, footprint_a, origin_a) = crop(img_a, size, anchor);
+            let (b, footprint_b, _) = crop(img_b, size, anchor);
+            Ok(Canvas { a, b, footprint_a, footprint_b, origin_a })
+        }
+        SizeMismatch::Resize => {
+            let resized = imageops::resize(img_b, dims_a.0, dims_a.1, FilterType::Triangle);
+            let full = Region { x: 0, y: 0, width: dims_a.0, height: dims_a.1 };
+            Ok(Canvas {
+                a: Cow::Borrowed(img_a),
+                b: Cow::Owned(resized),
+                footprint_a: full.clone(),
+                footprint_b: full,
+                origin_a: (0, 0),
+            })
+        }
+    }
+}
+
+/// An image brought onto a common size, with the area its original covers and the pixel of the
+/// original at that area's top-left corner.
+pub(crate) type Fitted<'a> = (Cow<'a, RgbaImage>, Region, (u32, u32));
+
+/// Brings both images to a common width, leaving their heights untouched.
+///
+/// Returns each image with its footprint on the widened (or narrowed) rows.
+pub(crate) fn fit_width<'a>(
+    img_a: &'a RgbaImage,
+    img_b: &'a RgbaImage,
+    policy: SizeMismatch,
+    anchor: Anchor,
+) -> Result<(Fitted<'a>, Fitted<'a>)> {
+    let full = |img: &RgbaImage, width: u32| (Region { x: 0, y: 0, width, height: img.height() }, (0, 0));
+    let (width_a, width_b) = (img_a.width(), img_b.width());
+
+    if width_a == width_b {
+        let ((footprint_a, origin_a), (footprint_b, origin_b)) = (full(img_a, width_a), full(img_b, width_b));
+        return Ok(((Cow::Borrowed(img_a), footprint_a, origin_a), (Cow::Borrowed(img_b), footprint_b, origin_b)));
+    }
+
+    match policy {
+        SizeMismatch::Fail => Err(DiffError::DimensionMismatch { a: img_a.dimensions(), b: img_b.dimensions() }),
+        SizeMismatch::Pad => {
+            let width = width_a.max(width_b);
+            Ok((pad(img_a, (width, img_a.height()), anchor), pad(img_b, (width, img_b.height()), anchor)))
+        }
+        SizeMismatch::Crop => {
+            let width = width_a.min(width_b);
+            Ok((crop(img_a, (width, img_a.height()), anchor), crop(img_b, (width, img_b.height()), anchor)))
+        }
+        SizeMismatch::Resize => {
+            let resized = imageops::resize(img_b, width_a, img_b.height(), FilterType::Triangle);
+            let ((footprint_a, origin_a), (footprint_b, origin_b)) = (full(img_a, width_a), full(img_b, width_a));
+            Ok(((Cow::Borrowed(img_a), footprint_a, origin_a), (Cow::Owned(resized), footprint_b, origin_b)))
+        }
+    }
+}
+
+fn pad(img: &RgbaImage, size: (u32, u32), anchor: Anchor) -> Fitted<'_> {
+    let (x, y) = anchor.offset(size, img.dimensions());
+    let footprint = Region { x, y, width: img.width(), height: img.height() };
+
+    if img.dimensions() == size {
+        return (Cow::Borrowed(img), footprint, (0, 0));
+    }
+
+    let mut canvas = RgbaImage::new(size.0, size.1);
+    imageops::overlay(&mut canvas, img, x as i64, y as i64);
+    (Cow::Owned(canvas), footprint, (0, 0))
+}
+
+fn crop(img: &RgbaImage, size: (u32, u32), anchor: Anchor) -> Fitted<'_> {
+    let footprint = Region { x: 0, y: 0, width: size.0, height: size.1 };
+    if img.dimensions() == size {
+        return (Cow::Borrowed(img), footprint, (0, 0));
+    }
+
+    let (x, y) = anchor.offset(img.dimensions(), size);
+    (Cow::Owned(imageops::crop_imm(img, x, y, size.0, size.1).to_image()), footprint, (x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_pad_center() {
+        let img_a = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let img_b = RgbaImage::from_pixel(6, 8, Rgba([0, 255, 0, 255]));
+
+        let canvas = fit(&img_a, &img_b, SizeMismatch::Pad, Anchor::Center).unwrap();
+        assert_eq!(canvas.b.dimensions(), (10, 10));
+        assert_eq!((canvas.footprint_b.x, canvas.footprint_b.y), (2, 1));
+        assert_eq!(*canvas.b.get_pixel(2, 1), Rgba([0, 255, 0, 255]));
+        assert_eq!(*canvas.b.get_pixel(1, 1), Rgba([0, 0, 0, 0]));
+        assert!(canvas.is_padding(0, 0));
+        assert!(!canvas.is_padding(5, 5));
+
+        let canvas = fit(&img_b, &img_a, SizeMismatch::Pad, Anchor::Center).unwrap();
+        assert_eq!(canvas.source_a(2, 1), Some((0, 0)));
+        assert_eq!(canvas.source_a(1, 1), None);
+    }
+
+    #[test]
+    fn test_crop_and_fail() {
+        let img_a = RgbaImage::from_fn(10, 10, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let img_b = RgbaImage::new(4, 4);
+
+        let canvas = fit(&img_a, &img_b, SizeMismatch::Crop, Anchor::BottomRight).unwrap();
+        assert_eq!(canvas.a.dimensions(), (4, 4));
+        assert_eq!(*canvas.a.get_pixel(0, 0), Rgba([6, 6, 0, 255]));
+        assert_eq!(canvas.source_a(0, 0), Some((6, 6)));
+        assert!(!canvas.has_padding());
+
+        let err = fit(&img_a, &img_b, SizeMismatch::Fail, Anchor::TopLeft).err().unwrap();
+        assert!(matches!(err, DiffError::DimensionMismatch { a: (10, 10), b: (4, 4) }));
+    }
+
+    #[test]
+    fn test_anchor_round_trip() {
+        for (anchor, name) in ANCHOR_NAMES {
+            assert_eq!(anchor.to_string(), name);
+            assert_eq!(name.parse::<Anchor>().unwrap(), anchor);
+        }
+    }
+}
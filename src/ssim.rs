@@ -0,0 +1,443 @@
+//! Structural similarity (SSIM) scores, maps and per-tile minimums.
+
+use crate::colormap;
+use crate::compare::Region;
+use image::buffer::ConvertBuffer;
+use image::{GrayImage, ImageBuffer, Luma, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Per-pixel SSIM values; 1.0 is structurally identical.
+pub type SsimMap = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Minimum SSIM within one tile of the SSIM map.
+#[derive(Serialize, Clone, Debug)]
+pub struct SsimRegion {
+    #[serde(flatten)]
+    pub region: Region,
+    pub min_ssim: f64,
+}
+
+/// How the SSIM map is rendered when saved.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SsimMapStyle {
+    /// White is identical, black is structurally different
+    #[default]
+    Gray,
+    /// Blue is identical, red is structurally different
+    Heatmap,
+}
+
+impl FromStr for SsimMapStyle {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gray" | "grey" | "grayscale" => Ok(SsimMapStyle::Gray),
+            "heatmap" => Ok(SsimMapStyle::Heatmap),
+            _ => Err(anyhow::anyhow!("Unknown SSIM map style '{}' (expected gray or heatmap)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SsimMapStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SsimMapStyle::Gray => f.write_str("gray"),
+            SsimMapStyle::Heatmap => f.write_str("heatmap"),
+        }
+    }
+}
+
+/// Which channels SSIM is computed over.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SsimColor {
+    /// Luma only (Rec. 601 weights)
+    Gray,
+    /// Each RGB channel separately; the lowest channel score wins
+    #[default]
+    Rgb,
+}
+
+impl FromStr for SsimColor {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gray" | "grey" | "grayscale" => Ok(SsimColor::Gray),
+            "rgb" => Ok(SsimColor::Rgb),
+            _ => Err(anyhow::anyhow!("Unknown SSIM colour mode '{}' (expected gray or rgb)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SsimColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SsimColor::Gray => f.write_str("gray"),
+            SsimColor::Rgb => f.write_str("rgb"),
+        }
+    }
+}
+
+/// Structural comparison algorithm.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SsimAlgorithm {
+    /// Mean SSIM over non-overlapping square windows
+    #[default]
+    Mssim,
+    /// 1 - normalized root mean squared error; the window size is not used
+    Rms,
+}
+
+impl FromStr for SsimAlgorithm {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mssim" | "ssim" => Ok(SsimAlgorithm::Mssim),
+            "rms" => Ok(SsimAlgorithm::Rms),
+            _ => Err(anyhow::anyhow!("Unknown SSIM algorithm '{}' (expected mssim or rms)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SsimAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SsimAlgorithm::Mssim => f.write_str("mssim"),
+            SsimAlgorithm::Rms => f.write_str("rms"),
+        }
+    }
+}
+
+/// Settings for the structural similarity pass.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SsimOptions {
+    /// Skip SSIM entirely; `DiffResult::ssim_score` is then `None`
+    pub enabled: bool,
+    pub color: SsimColor,
+    pub algorithm: SsimAlgorithm,
+    /// Window size in pixels for `SsimAlgorithm::Mssim`
+    pub window: u32,
+    /// Whether to keep the per-pixel SSIM map in `DiffResult::ssim_map`
+    pub generate_map: bool,
+    /// Tile size used to report per-region minimum SSIM
+    pub tile_size: u32,
+}
+
+impl Default for SsimOptions {
+    fn default() -> Self {
+        SsimOptions {
+            enabled: true,
+            color: SsimColor::default(),
+            algorithm: SsimAlgorithm::default(),
+            window: 8,
+            generate_map: false,
+            tile_size: 64,
+        }
+    }
+}
+
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Computes the structural similarity score and a per-pixel map holding the worst channel.
+///
+/// With the default options this matches `image_compare`'s RGB `MSSIMSimple`: SSIM over
+/// non-overlapping 8x8 windows, averaged by window area, with the lowest channel score winning.
+/// `image_compare` is not called directly because its windows are fixed at 8x8 and its similarity
+/// image type is not exported, so the map is only reachable as an 8-bit colour map clamped to
+/// 0.0-1.0, which drops negative SSIM and rounds small structural changes up to 1.0.
+pub fn structural_similarity(
+    img_a: &RgbaImage,
+    img_b: &RgbaImage,
+    options: &SsimOptions,
+) -> Result<(f64, SsimMap), String> {
+    if img_a.dimensions() != img_b.dimensions() {
+        return Err("The dimensions of the input images are not identical".to_string());
+    }
+    let (width, height) = img_a.dimensions();
+    if width == 0 || height == 0 {
+        return Err("Cannot compute SSIM of an empty image".to_string());
+    }
+    if options.algorithm == SsimAlgorithm::Mssim && options.window == 0 {
+        return Err("SSIM window size must be at least 1".to_string());
+    }
+
+    let planes_a = planes(img_a, options.color);
+    let planes_b = planes(img_b, options.color);
+
+    let (score, map) = match options.algorithm {
+        SsimAlgorithm::Mssim => mssim(&planes_a, &planes_b, width, height, options.window),
+        SsimAlgorithm::Rms => rms(&planes_a, &planes_b, width, height),
+    };
+
+    if !score.is_finite() {
+        return Err(format!("SSIM computation produced a non-finite score ({})", score));
+    }
+
+    Ok((score, map))
+}
+
+// Splits the image into one 8-bit plane per compared channel, like the buffers `image_compare`
+// works on, so an 8K pair needs about 200MB instead of 1.6GB of f64 planes. Window statistics
+// are accumulated in f64 as pixels are read.
+fn planes(img: &RgbaImage, color: SsimColor) -> Vec<Vec<u8>> {
+    match color {
+        SsimColor::Rgb => (0..3)
+            .map(|c| img.pixels().map(|p| p[c]).collect())
+            .collect(),
+        SsimColor::Gray => {
+            let luma: GrayImage = img.convert();
+            vec![luma.into_raw()]
+        }
+    }
+}
+
+fn mssim(planes_a: &[Vec<u8>], planes_b: &[Vec<u8>], width: u32, height: u32, window: u32) -> (f64, SsimMap) {
+    let mut map = SsimMap::new(width, height);
+
+    let windows: Vec<(u32, u32, u32, u32)> = (0..height)
+        .step_by(window as usize)
+        .flat_map(|y| {
+            (0..width)
+                .step_by(window as usize)
+                .map(move |x| (x, y, window.min(width - x), window.min(height - y)))
+        })
+        .collect();
+
+    let results: Vec<Vec<f64>> = windows
+        .par_iter()
+        .map(|&win| {
+            planes_a.iter()
+                .zip(planes_b)
+                .map(|(a, b)| ssim_for_window(a, b, width, win))
+                .collect()
+        })
+        .collect();
+
+    let mut channel_sums = vec![0.0; planes_a.len()];
+    for (&(x, y, w, h), ssim) in windows.iter().zip(&results) {
+        let area = (w * h) as f64;
+        for (sum, value) in channel_sums.iter_mut().zip(ssim) {
+            *sum += value * area;
+        }
+
+        let worst = ssim.iter().copied().fold(f64::INFINITY, f64::min) as f32;
+        for py in y..y + h {
+            for px in x..x + w {
+                map.put_pixel(px, py, Luma([worst]));
+            }
+        }
+    }
+
+    let area = (width as f64) * (height as f64);
+    let score = channel_sums.iter().map(|sum| sum / area).fold(1.0, f64::min);
+
+    (score, map)
+}
+
+fn ssim_for_window(plane_a: &[u8], plane_b: &[u8], width: u32, (x, y, w, h): (u32, u32, u32, u32)) -> f64 {
+    let n = (w * h) as f64;
+    let pixels = || {
+        (y..y + h).flat_map(move |py| {
+            let row = (py * width) as usize;
+            (row + x as usize..row + (x + w) as usize).map(move |i| (plane_a[i] as f64, plane_b[i] as f64))
+        })
+    };
+
+    let (sum_a, sum_b) = pixels().fold((0.0, 0.0), |(sa, sb), (a, b)| (sa + a, sb + b));
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+
+    let (var_a, var_b, cov) = pixels().fold((0.0, 0.0, 0.0), |(va, vb, cv), (a, b)| {
+        let (da, db) = (a - mean_a, b - mean_b);
+        (va + da * da, vb + db * db, cv + da * db)
+    });
+
+    let numerator = (2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2);
+    let denominator = (mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2);
+    numerator / denominator
+}
+
+fn rms(planes_a: &[Vec<u8>], planes_b: &[Vec<u8>], width: u32, height: u32) -> (f64, SsimMap) {
+    let mut map = SsimMap::from_pixel(width, height, Luma([1.0]));
+    let mut score: f64 = 1.0;
+
+    for (a, b) in planes_a.iter().zip(planes_b) {
+        let mut sum_sq = 0.0;
+        for (i, (pa, pb)) in a.iter().zip(b).enumerate() {
+            let diff = pa.abs_diff(*pb) as f64 / 255.0;
+            sum_sq += diff * diff;
+
+            let pixel = map.get_pixel_mut(i as u32 % width, i as u32 / width);
+            pixel[0] = pixel[0].min(1.0 - diff as f32);
+        }
+        score = score.min(1.0 - (sum_sq / a.len() as f64).sqrt());
+    }
+
+    (score, map)
+}
+
+/// Splits the map into `tile_size` squares and returns those whose minimum SSIM is below 1.0.
+pub fn low_ssim_regions(map: &SsimMap, tile_size: u32) -> Vec<SsimRegion> {
+    let tile_size = tile_size.max(1);
+    let (width, height) = map.dimensions();
+    let mut regions = Vec::new();
+
+    for ty in (0..height).step_by(tile_size as usize) {
+        for tx in (0..width).step_by(tile_size as usize) {
+            let tw = tile_size.min(width - tx);
+            let th = tile_size.min(height - ty);
+
+            let mut min_ssim = f32::INFINITY;
+            for y in ty..ty + th {
+                for x in tx..tx + tw {
+                    min_ssim = min_ssim.min(map.get_pixel(x, y)[0]);
+                }
+            }
+
+            // Allow for float noise on structurally identical tiles
+            if min_ssim < 1.0 - 1e-6 {
+                regions.push(SsimRegion {
+                    region: Region { x: tx, y: ty, width: tw, height: th },
+                    min_ssim: min_ssim as f64,
+                });
+            }
+        }
+    }
+
+    regions
+}
+
+/// Renders the SSIM map for saving as an image.
+pub fn render_ssim_map(map: &SsimMap, style: SsimMapStyle) -> RgbaImage {
+    match style {
+        SsimMapStyle::Gray => {
+            let gray = GrayImage::from_fn(map.width(), map.height(), |x, y| {
+                Luma([(map.get_pixel(x, y)[0].clamp(0.0, 1.0) * 255.0) as u8])
+            });
+            gray.convert()
+        }
+        SsimMapStyle::Heatmap => RgbaImage::from_fn(map.width(), map.height(), |x, y| {
+            colormap::heat(1.0 - map.get_pixel(x, y)[0])
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_low_ssim_regions_localized() {
+        let img_a = RgbaImage::from_fn(64, 64, |x, y| {
+            let v = ((x * 7 + y * 13) % 256) as u8;
+            Rgba([v, v, v, 255])
+        });
+        let mut img_b = img_a.clone();
+        for y in 40..48 {
+            for x in 40..48 {
+                img_b.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+
+        let (score, map) = structural_similarity(&img_a, &img_b, &SsimOptions::default()).unwrap();
+        assert!(score < 1.0);
+        assert_eq!(map.dimensions(), (64, 64));
+
+        let regions = low_ssim_regions(&map, 32);
+        assert_eq!(regions.len(), 1);
+        assert_eq!((regions[0].region.x, regions[0].region.y), (32, 32));
+        assert!(regions[0].min_ssim < 0.5);
+    }
+
+    #[test]
+    fn test_matches_image_compare() {
+        let fixtures = [
+            (
+                RgbaImage::from_fn(37, 21, |x, y| Rgba([(x * 5) as u8, (y * 11) as u8, ((x + y) * 3) as u8, 255])),
+                RgbaImage::from_fn(37, 21, |x, y| Rgba([(x * 5) as u8, (y * 9) as u8, ((x * y) % 256) as u8, 255])),
+            ),
+            (
+                RgbaImage::from_fn(64, 40, |x, y| Rgba([((x * 31 + y * 17) % 251) as u8, (x ^ y) as u8, 90, 255])),
+                RgbaImage::from_fn(64, 40, |x, y| Rgba([((x * 29 + y * 19) % 241) as u8, (x | y) as u8, 90, 255])),
+            ),
+            (
+                RgbaImage::from_pixel(5, 3, Rgba([200, 10, 10, 255])),
+                RgbaImage::from_fn(5, 3, |x, _| Rgba([200, 10, (x * 60) as u8, 255])),
+            ),
+        ];
+        let cases = [
+            (SsimColor::Rgb, SsimAlgorithm::Mssim, image_compare::Algorithm::MSSIMSimple),
+            (SsimColor::Gray, SsimAlgorithm::Mssim, image_compare::Algorithm::MSSIMSimple),
+            (SsimColor::Rgb, SsimAlgorithm::Rms, image_compare::Algorithm::RootMeanSquared),
+            (SsimColor::Gray, SsimAlgorithm::Rms, image_compare::Algorithm::RootMeanSquared),
+        ];
+
+        let rgb = |img: &RgbaImage| -> image::RgbImage { img.convert() };
+        let gray = |img: &RgbaImage| -> GrayImage { img.convert() };
+
+        for (img_a, img_b) in &fixtures {
+            for (color, algorithm, reference) in &cases {
+                let expected = match color {
+                    SsimColor::Rgb => image_compare::rgb_similarity_structure(reference, &rgb(img_a), &rgb(img_b)),
+                    SsimColor::Gray => image_compare::gray_similarity_structure(reference, &gray(img_a), &gray(img_b)),
+                }
+                .unwrap();
+
+                let options = SsimOptions { color: *color, algorithm: *algorithm, ..Default::default() };
+                let (score, map) = structural_similarity(img_a, img_b, &options).unwrap();
+                let label = format!("{:?} {:?}", color, algorithm);
+                assert!((score - expected.score).abs() < 1e-6, "{}: {} vs {}", label, score, expected.score);
+
+                // The reference map is only available as 8-bit channels clamped to 0.0-1.0
+                let reference_map = expected.image.to_color_map().to_rgb8();
+                for (ours, theirs) in map.pixels().zip(reference_map.pixels()) {
+                    let worst = theirs.0.iter().copied().min().unwrap();
+                    assert_eq!((ours[0].clamp(0.0, 1.0) * 255.0) as u8, worst, "{}", label);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_identical_has_no_regions() {
+        let img = RgbaImage::from_pixel(16, 16, Rgba([10, 20, 30, 255]));
+        let (_, map) = structural_similarity(&img, &img, &SsimOptions::default()).unwrap();
+        assert!(low_ssim_regions(&map, 8).is_empty());
+    }
+
+    #[test]
+    fn test_gray_and_rms() {
+        let img_a = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        let img_b = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+
+        let gray = SsimOptions { color: SsimColor::Gray, ..Default::default() };
+        let (score, map) = structural_similarity(&img_a, &img_b, &gray).unwrap();
+        assert!(score < 0.01);
+        assert_eq!(map.dimensions(), (4, 4));
+
+        let rms = SsimOptions { algorithm: SsimAlgorithm::Rms, ..Default::default() };
+        let (score, map) = structural_similarity(&img_a, &img_b, &rms).unwrap();
+        assert_eq!(score, 0.0);
+        assert_eq!(map.get_pixel(0, 0)[0], 0.0);
+    }
+
+    #[test]
+    fn test_edge_cases_are_errors() {
+        let empty = RgbaImage::new(0, 0);
+        assert!(structural_similarity(&empty, &empty, &SsimOptions::default()).is_err());
+
+        let tiny = RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 255]));
+        let (score, _) = structural_similarity(&tiny, &tiny, &SsimOptions::default()).unwrap();
+        assert_eq!(score, 1.0);
+
+        let no_window = SsimOptions { window: 0, ..Default::default() };
+        assert!(structural_similarity(&tiny, &tiny, &no_window).is_err());
+    }
+}
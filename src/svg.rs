@@ -0,0 +1,219 @@
+//! Rasterization of SVG inputs, so vector assets can be compared like any other image.
+//!
+//! Files are rendered with resvg, a pure-Rust renderer, at their intrinsic size scaled by the
+//! requested DPI, or at an explicit pixel size.
+
+use crate::error::{DiffError, Result};
+use crate::options::Color;
+use image::error::{DecodingError, ImageFormatHint};
+use image::{ImageError, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// CSS pixels per inch, the resolution at which SVG user units map one-to-one onto pixels.
+const CSS_DPI: f32 = 96.0;
+// Enough to get past an XML declaration, a doctype and a licence comment
+const SNIFF_LEN: u64 = 1024;
+
+/// Settings for rasterizing SVG inputs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SvgOptions {
+    /// Output size in pixels; overrides `dpi`
+    pub size: Option<SvgSize>,
+    /// Resolution at which the document's intrinsic size is rendered
+    pub dpi: f32,
+    /// Opaque background painted under the drawing; transparent if `None`
+    pub background: Option<Color>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions { size: None, dpi: CSS_DPI, background: None }
+    }
+}
+
+/// Raster size parsed from `W` (height follows the aspect ratio) or `WxH`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SvgSize {
+    pub width: u32,
+    pub height: Option<u32>,
+}
+
+impl FromStr for SvgSize {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = match s.split_once(['x', 'X']) {
+            Some((w, h)) => (w.trim().parse::<u32>()?, Some(h.trim().parse::<u32>()?)),
+            None => (s.trim().parse::<u32>()?, None),
+        };
+        if width == 0 || height == Some(0) {
+            return Err(anyhow::anyhow!("SVG size must be positive"));
+        }
+        Ok(SvgSize { width, height })
+    }
+}
+
+impl fmt::Display for SvgSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.height {
+            Some(height) => write!(f, "{}x{}", self.width, height),
+            None => write!(f, "{}", self.width),
+        }
+    }
+}
+
+/// Whether `path` is an SVG document, by its `.svg`/`.svgz` extension or an `<svg` root element.
+///
+/// Only consulted for files whose magic bytes match no raster format, so this never shadows an
+/// image decoder. The root element may follow a BOM, an XML declaration, comments and a doctype;
+/// documents that merely contain an `<svg` tag, such as HTML or Markdown, are not SVG.
+pub fn is_svg(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    if matches!(extension.as_deref(), Some("svg" | "svgz")) {
+        return true;
+    }
+
+    let mut head = Vec::new();
+    let read = std::fs::File::open(path).and_then(|file| file.take(SNIFF_LEN).read_to_end(&mut head));
+    read.is_ok() && has_svg_root(&String::from_utf8_lossy(&head))
+}
+
+/// Whether the first element of an XML prolog is `<svg`.
+fn has_svg_root(head: &str) -> bool {
+    let mut rest = head.strip_prefix('\u{feff}').unwrap_or(head);
+    loop {
+        rest = rest.trim_start();
+        let end = if rest.starts_with("<?") {
+            rest.find("?>").map(|i| i + 2)
+        } else if rest.starts_with("<!--") {
+            rest.find("-->").map(|i| i + 3)
+        } else if rest.starts_with("<!") {
+            // A doctype may carry an internal subset in brackets, which can itself contain `>`
+            match (rest.find('['), rest.find('>')) {
+                (Some(open), Some(close)) if open < close => rest.find("]>").map(|i| i + 2),
+                (_, close) => close.map(|i| i + 1),
+            }
+        } else {
+            let tag = rest.strip_prefix("<svg");
+            return tag.is_some_and(|t| t.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/'));
+        };
+        match end {
+            Some(end) => rest = &rest[end..],
+            None => return false,
+        }
+    }
+}
+
+/// Renders an SVG (or gzip-compressed SVGZ) file to an RGBA image.
+pub fn rasterize(path: &Path, options: &SvgOptions) -> Result<RgbaImage> {
+    let data = std::fs::read(path).map_err(|e| DiffError::from_image(path, ImageError::IoError(e)))?;
+    let decode_err = |reason: String| {
+        let err = DecodingError::new(ImageFormatHint::Name("SVG".to_string()), reason);
+        DiffError::from_image(path, ImageError::Decoding(err))
+    };
+
+    let usvg_options = usvg::Options {
+        resources_dir: path.parent().map(Path::to_path_buf),
+        fontdb: system_fonts(),
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_data(&data, &usvg_options).map_err(|e| decode_err(e.to_string()))?;
+
+    let intrinsic = tree.size();
+    let (width, height, scale_x, scale_y) = match options.size {
+        None => {
+            let scale = options.dpi / CSS_DPI;
+            let (width, height) = (intrinsic.width() * scale, intrinsic.height() * scale);
+            (width.ceil() as u32, height.ceil() as u32, scale, scale)
+        }
+        Some(SvgSize { width, height: None }) => {
+            let scale = width as f32 / intrinsic.width();
+            (width, (intrinsic.height() * scale).ceil() as u32, scale, scale)
+        }
+        Some(SvgSize { width, height: Some(height) }) => {
+            (width, height, width as f32 / intrinsic.width(), height as f32 / intrinsic.height())
+        }
+    };
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| decode_err(format!("cannot rasterize at {}x{} pixels", width, height)))?;
+    if let Some(Color([r, g, b])) = options.background {
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, 255));
+    }
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale_x, scale_y), &mut pixmap.as_mut());
+
+    // tiny-skia stores premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("pixmap size matches its buffer"))
+}
+
+/// System fonts for `<text>` elements, loaded once on first use.
+fn system_fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            Arc::new(db)
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const SQUARE: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2">
+        <rect x="0" y="0" width="2" height="2" fill="#ff0000"/>
+    </svg>"##;
+
+    #[test]
+    fn test_rasterize_size_dpi_and_background() {
+        // No extension, so the file is recognised from its content
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), SQUARE).unwrap();
+        assert!(is_svg(file.path()));
+
+        let img = rasterize(file.path(), &SvgOptions::default()).unwrap();
+        assert_eq!(img.dimensions(), (4, 2));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(img.get_pixel(3, 1)[3], 0);
+
+        let options = SvgOptions { dpi: 192.0, background: Some(Color([0, 0, 255])), ..SvgOptions::default() };
+        let img = rasterize(file.path(), &options).unwrap();
+        assert_eq!(img.dimensions(), (8, 4));
+        assert_eq!(*img.get_pixel(7, 3), Rgba([0, 0, 255, 255]));
+
+        let size = "8".parse::<SvgSize>().unwrap();
+        let img = rasterize(file.path(), &SvgOptions { size: Some(size), ..SvgOptions::default() }).unwrap();
+        assert_eq!(img.dimensions(), (8, 4));
+        let size = "2x6".parse::<SvgSize>().unwrap();
+        let img = rasterize(file.path(), &SvgOptions { size: Some(size), ..SvgOptions::default() }).unwrap();
+        assert_eq!(img.dimensions(), (2, 6));
+        assert!("0x4".parse::<SvgSize>().is_err());
+
+        let prolog = "\u{feff}<?xml version=\"1.0\"?>\n<!-- licence -->\n<!DOCTYPE svg [<!ENTITY a \"b\">]>\n<svg/>";
+        assert!(has_svg_root(prolog));
+        assert!(!has_svg_root("<!DOCTYPE html>\n<html><body><svg></svg></body></html>"));
+        assert!(!has_svg_root("# Notes\n\n<svg width=\"1\"/>"));
+        assert!(!has_svg_root("<svgfoo/>"));
+
+        std::fs::write(file.path(), "<svg").unwrap();
+        let err = rasterize(file.path(), &SvgOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), "decode");
+    }
+}
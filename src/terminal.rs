@@ -1,9 +1,13 @@
 use colored::*;
 use image::{DynamicImage, GenericImageView, Rgba};
+use image_diff::compare::{Region, BOX_COLOR};
+use image_diff::render::draw_box;
 use terminal_size::{terminal_size, Width};
 use viuer::{print, Config};
 
-pub fn print_preview(img: &DynamicImage) {
+/// Prints `img` to the terminal, outlining `boxes` (in image coordinates) so they stay
+/// visible after downscaling.
+pub fn print_preview(img: &DynamicImage, boxes: &[Region]) {
     let (tw, th) = if let Some((Width(w), h)) = terminal_size() {
         (w as u32, h.0 as u32)
     } else {
@@ -21,7 +25,14 @@ pub fn print_preview(img: &DynamicImage) {
         ..Default::default()
     };
 
-    if print(img, &conf).is_ok() {
+    // One terminal cell's worth of source pixels keeps outlines from vanishing when scaled down
+    let mut outlined = img.to_rgba8();
+    let thickness = (img.width() / tw.max(1)).max(1);
+    for b in boxes {
+        draw_box(&mut outlined, b, thickness, BOX_COLOR);
+    }
+
+    if print(&DynamicImage::ImageRgba8(outlined), &conf).is_ok() {
         return;
     }
 
@@ -33,13 +44,28 @@ pub fn print_preview(img: &DynamicImage) {
     let target_width = tw.min(width).min(80); // Cap width for readability
     let target_height = (target_width as f32 * aspect_ratio) as u32;
     
-    let resized = img.resize_exact(target_width, target_height * 2, image::imageops::FilterType::Nearest);
+    let mut resized = img
+        .resize_exact(target_width, target_height * 2, image::imageops::FilterType::Nearest)
+        .to_rgba8();
+
+    let (sx, sy) = (resized.width() as f32 / width as f32, resized.height() as f32 / height as f32);
+    for b in boxes {
+        let x = (b.x as f32 * sx) as u32;
+        let y = (b.y as f32 * sy) as u32;
+        let scaled = Region {
+            x,
+            y,
+            width: (((b.x + b.width) as f32 * sx).ceil() as u32).saturating_sub(x).max(1),
+            height: (((b.y + b.height) as f32 * sy).ceil() as u32).saturating_sub(y).max(1),
+        };
+        draw_box(&mut resized, &scaled, 1, BOX_COLOR);
+    }
     
     for y in (0..resized.height()).step_by(2) {
         for x in 0..resized.width() {
-            let top = resized.get_pixel(x, y);
+            let top = *resized.get_pixel(x, y);
             let bottom = if y + 1 < resized.height() {
-                resized.get_pixel(x, y + 1)
+                *resized.get_pixel(x, y + 1)
             } else {
                 Rgba([0, 0, 0, 0])
             };